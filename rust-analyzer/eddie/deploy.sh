@@ -25,6 +25,7 @@ cargo build --release
 sudo supervisorctl stop eddie
 
 cp target/release/eddie /usr/local/bin/eddie
+cp fleet.toml /usr/local/bin/fleet.toml
 
 # Restart the application via Supervisor
 echo "Restarting application via Supervisor..."
@@ -0,0 +1,136 @@
+// Virtual account of a bot: realized net P&L is compounded into the balance, the open position
+// adds its unrealized P&L to the equity, and an equity point is taken every `equity_interval_ms`.
+// Points are stored in the `equity:{bot}` sorted sets, scored by event time (ms).
+
+use serde::{Deserialize, Serialize};
+
+pub const EQUITY_KEY: &str = "equity";
+
+pub const DEFAULT_EQUITY_INTERVAL_MS: i64 = 60_000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EquityPoint {
+    pub time: i64,
+    pub balance: f64,
+    pub unrealized_pnl: f64,
+    pub equity: f64,
+    // Distance from the highest equity seen so far, in quote currency.
+    pub drawdown: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Account {
+    pub starting_balance: f64,
+    pub balance: f64,
+    // When set, balance percentage sizing uses the current balance instead of the starting one.
+    pub reinvest: bool,
+    pub unrealized_pnl: f64,
+    pub peak_equity: f64,
+    pub max_drawdown: f64,
+    pub equity_interval_ms: i64,
+    pub last_point_time: i64,
+}
+
+impl Account {
+    pub fn new(starting_balance: f64, reinvest: bool) -> Self {
+        Self {
+            starting_balance,
+            balance: starting_balance,
+            reinvest,
+            unrealized_pnl: 0.0,
+            peak_equity: starting_balance,
+            max_drawdown: 0.0,
+            equity_interval_ms: DEFAULT_EQUITY_INTERVAL_MS,
+            last_point_time: 0,
+        }
+    }
+
+    pub fn equity(&self) -> f64 {
+        self.balance + self.unrealized_pnl
+    }
+
+    pub fn drawdown(&self) -> f64 {
+        self.peak_equity - self.equity()
+    }
+
+    // Balance used to size new positions.
+    pub fn sizing_balance(&self) -> f64 {
+        if self.reinvest {
+            self.balance
+        } else {
+            self.starting_balance
+        }
+    }
+
+    pub fn realize(&mut self, net_pnl: f64) {
+        self.balance += net_pnl;
+        self.unrealized_pnl = 0.0;
+        self.track_drawdown();
+    }
+
+    // Update the unrealized P&L of the open position, returns an equity point when one is due.
+    pub fn mark(&mut self, time: i64, unrealized_pnl: f64) -> Option<EquityPoint> {
+        self.unrealized_pnl = unrealized_pnl;
+        self.track_drawdown();
+
+        if time - self.last_point_time < self.equity_interval_ms {
+            return None;
+        }
+
+        Some(self.point(time))
+    }
+
+    pub fn point(&mut self, time: i64) -> EquityPoint {
+        self.last_point_time = time;
+
+        EquityPoint {
+            time,
+            balance: self.balance,
+            unrealized_pnl: self.unrealized_pnl,
+            equity: self.equity(),
+            drawdown: self.drawdown(),
+        }
+    }
+
+    fn track_drawdown(&mut self) {
+        self.peak_equity = self.peak_equity.max(self.equity());
+        self.max_drawdown = self.max_drawdown.max(self.drawdown());
+    }
+}
+
+pub fn equity_key(bot: &str) -> String {
+    format!("{}:{}", EQUITY_KEY, bot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_equity_and_drawdown() {
+        let mut account = Account::new(1_000.0, false);
+
+        assert!(account.mark(30_000, 25.0).is_none());
+
+        let point = account.mark(60_000, -40.0).unwrap();
+        assert_eq!(point.equity, 960.0);
+        assert_eq!(point.drawdown, 65.0);
+
+        account.realize(-40.0);
+        assert_eq!(account.balance, 960.0);
+        assert_eq!(account.sizing_balance(), 1_000.0);
+        assert_eq!(account.max_drawdown, 65.0);
+
+        assert!(account.mark(90_000, 0.0).is_none());
+        assert_eq!(account.mark(120_000, 10.0).unwrap().equity, 970.0);
+    }
+
+    #[test]
+    fn test_reinvest() {
+        let mut account = Account::new(1_000.0, true);
+        account.realize(150.0);
+
+        assert_eq!(account.sizing_balance(), 1_150.0);
+        assert_eq!(account.peak_equity, 1_150.0);
+    }
+}
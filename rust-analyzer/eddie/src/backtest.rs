@@ -0,0 +1,651 @@
+// Backtest mode: historical klines are replayed through the fleet bots, in event time order and
+// without writing anything to redis. Klines come from the klines:{symbol}:{interval} sorted sets
+// written by Hooks::kline or from their `redis-cli --csv` exports, signal bots read their
+// indicator history the same way. The same input always gives the same trades.
+//
+//   eddie backtest --symbol BTCUSDT --interval 1s --csv klines_btc_usdt.csv \
+//       --indicators indicators:BTCUSDT=indicators_btc_usdt.csv --trades trades.json
+
+use crate::candles::Candles;
+use crate::dumps;
+use crate::fleet::{self, Bot, FleetConfig};
+use crate::ledger::{ExitReason, TradeRecord};
+use crate::metrics::{self, Metrics};
+use crate::processors::binance_hooks::binance_hooks::KlineData;
+use crate::signal_trading::{SignalSource, SignalTrading};
+use crate::simple_trading::REDIS_URL;
+use crate::strategy::{self, interval_ms, Kline, TICK_INTERVAL_MS};
+use crate::utils::strategy_builder::strategy_builder::{
+    load_strategy_config_from_file, IndicatorData, Signal, StrategyBuilder, StrategyConfig,
+};
+use redis::Commands;
+use serde::Serialize;
+use std::collections::HashMap;
+
+pub fn klines_key(symbol: &str, interval: &str) -> String {
+    format!("klines:{}:{}", symbol, interval)
+}
+
+pub fn load_redis_klines(
+    con: &mut redis::Connection,
+    symbol: &str,
+    interval: &str,
+    from: i64,
+    to: i64,
+) -> Result<Vec<Kline>, Box<dyn std::error::Error>> {
+    let values: Vec<String> = con.zrangebyscore(klines_key(symbol, interval), from, to)?;
+
+    parse_klines(symbol, interval, values)
+}
+
+// `redis-cli --csv ZRANGE klines:{symbol}:{interval} 0 -1` export, one JSON KlineData per line.
+pub fn load_csv_klines(
+    path: &str,
+    symbol: &str,
+    interval: &str,
+) -> Result<Vec<Kline>, Box<dyn std::error::Error>> {
+    parse_klines(symbol, interval, read_csv_values(path)?)
+}
+
+fn parse_klines(
+    symbol: &str,
+    interval: &str,
+    values: Vec<String>,
+) -> Result<Vec<Kline>, Box<dyn std::error::Error>> {
+    let mut klines = Vec::with_capacity(values.len());
+
+    for value in values {
+        let data: KlineData = serde_json::from_str(&value)?;
+
+        match Kline::from_data(symbol, interval, &data) {
+            Some(kline) => klines.push(kline),
+            None => return Err(format!("invalid kline {}", value).into()),
+        }
+    }
+
+    klines.sort_by_key(|kline| kline.close_time);
+    klines.dedup_by_key(|kline| kline.close_time);
+
+    Ok(klines)
+}
+
+pub fn load_redis_indicators(
+    con: &mut redis::Connection,
+    key: &str,
+) -> Result<Vec<IndicatorData>, Box<dyn std::error::Error>> {
+    let values: Vec<String> = con.zrange(key, 0, -1)?;
+
+    parse_indicators(values)
+}
+
+// `redis-cli --csv ZRANGE indicators:{symbol} 0 -1` export, as in the README.
+pub fn load_csv_indicators(path: &str) -> Result<Vec<IndicatorData>, Box<dyn std::error::Error>> {
+    parse_indicators(read_csv_values(path)?)
+}
+
+fn parse_indicators(values: Vec<String>) -> Result<Vec<IndicatorData>, Box<dyn std::error::Error>> {
+    let mut history = values
+        .iter()
+        .map(|value| serde_json::from_str::<IndicatorData>(value))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    history.sort_by_key(|data| data.time_ms());
+
+    Ok(history)
+}
+
+// First field of every line, the member of a `redis-cli --csv` export (the score may follow).
+fn read_csv_values(path: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_path(path)?;
+
+    let mut values = Vec::new();
+
+    for record in reader.records() {
+        if let Some(value) = record?.get(0).filter(|value| !value.is_empty()) {
+            values.push(value.to_string());
+        }
+    }
+
+    Ok(values)
+}
+
+// StrategyBuilder fed with the indicator points known at the close of each kline.
+pub struct HistorySignals {
+    builder: StrategyBuilder,
+    // newest first, `visible` points of the end are in the past of the current kline.
+    history: Vec<IndicatorData>,
+    visible: usize,
+    // Holds like RedisSignals when the newest visible point is older than this at the kline close.
+    max_age_ms: i64,
+}
+
+impl HistorySignals {
+    pub fn new(config: StrategyConfig, mut history: Vec<IndicatorData>, max_age_ms: i64) -> Self {
+        history.sort_by_key(|data| std::cmp::Reverse(data.time_ms()));
+
+        Self {
+            builder: StrategyBuilder::new(REDIS_URL, config).unwrap(),
+            history,
+            visible: 0,
+            max_age_ms,
+        }
+    }
+}
+
+impl SignalSource for HistorySignals {
+    fn signal(&mut self, kline: &Kline) -> Result<Signal, Box<dyn std::error::Error>> {
+        while self.visible < self.history.len() {
+            let next = &self.history[self.history.len() - 1 - self.visible];
+
+            if next.time_ms() > kline.close_time {
+                break;
+            }
+
+            self.visible += 1;
+        }
+
+        let start = self.history.len() - self.visible;
+
+        if let Some(newest) = self.history.get(start).filter(|_| self.visible > 0) {
+            if kline.close_time - newest.time_ms() > self.max_age_ms {
+                return Ok(Signal::HOLD);
+            }
+        }
+
+        self.builder.evaluate_history(&self.history[start..])
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Summary {
+    pub trades: usize,
+    pub wins: usize,
+    pub win_rate: f64,
+    pub gross_pnl: f64,
+    pub costs: f64,
+    pub net_pnl: f64,
+    // Net wins over net losses, infinite without losing trades.
+    pub profit_factor: f64,
+    pub max_drawdown: f64,
+    pub final_balance: f64,
+}
+
+impl Summary {
+    // The headline figures of `metrics`, with the drawdown and balance of the account.
+    pub fn new(metrics: &Metrics, max_drawdown: f64, final_balance: f64) -> Self {
+        Self {
+            trades: metrics.trades,
+            wins: metrics.wins,
+            win_rate: metrics.win_rate,
+            gross_pnl: metrics.gross_pnl,
+            costs: metrics.costs,
+            net_pnl: metrics.net_pnl,
+            profit_factor: metrics.profit_factor,
+            max_drawdown,
+            final_balance,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BotReport {
+    pub name: String,
+    pub family: String,
+    pub summary: Summary,
+    pub metrics: Metrics,
+    pub trades: Vec<TradeRecord>,
+}
+
+// Strategy of a signal bot replayed on an indicator history. There is no order flow history, an
+// order_flow indicator would score differently than live.
+pub fn history_strategy(path: &str) -> Result<StrategyConfig, Box<dyn std::error::Error>> {
+    let strategy = load_strategy_config_from_file(path)?;
+
+    if let Some(key) = strategy.order_flow_key() {
+        return Err(format!("{}: order_flow ({}) cannot be backtested, no history", path, key).into());
+    }
+
+    Ok(strategy)
+}
+
+// Build the backtest bots of `symbol`, signal bots read the indicator history of their key.
+pub fn build_bots(
+    config: &FleetConfig,
+    symbol: &str,
+    indicators: &HashMap<String, Vec<IndicatorData>>,
+) -> Result<Vec<Bot>, Box<dyn std::error::Error>> {
+    let mut bots = Vec::new();
+
+    for bot_config in config.bots.iter().filter(|bot| bot.symbol == symbol) {
+        let mut trader = bot_config.build();
+        trader.persist = false;
+
+        let bot = match &bot_config.signal {
+            Some(signal) => {
+                let strategy = history_strategy(&signal.strategy)?;
+                let history = indicators.get(&signal.key).cloned().unwrap_or_default();
+                let source = HistorySignals::new(strategy, history, signal.max_age_ms());
+
+                Bot::Signal(SignalTrading::new(trader, &signal.interval, Box::new(source)))
+            }
+            None => Bot::Movement(trader),
+        };
+
+        bots.push(bot);
+    }
+
+    Ok(bots)
+}
+
+// Prices a bar went through before its close, for klines longer than a tick: open, then the
+// extreme closest to the open first. The close itself is the tick dispatched with the kline.
+fn intrabar_ticks(kline: &Kline) -> Vec<(f64, i64)> {
+    let length = interval_ms(&kline.interval).unwrap_or(1_000);
+
+    if length <= 1_000 {
+        return Vec::new();
+    }
+
+    let step = length / 3;
+
+    let extremes = if kline.high - kline.open < kline.open - kline.low {
+        [kline.high, kline.low]
+    } else {
+        [kline.low, kline.high]
+    };
+
+    vec![
+        (kline.open, kline.open_time),
+        (extremes[0], kline.open_time + step),
+        (extremes[1], kline.open_time + 2 * step),
+    ]
+}
+
+fn aggregated_candles(bots: &[Bot], klines: &[Kline]) -> Candles {
+    let Some(source) = klines.first().map(|kline| kline.interval.to_string()) else {
+        return Candles::new("", &[]);
+    };
+
+    let length = interval_ms(&source).unwrap_or(TICK_INTERVAL_MS);
+    let mut intervals = Vec::new();
+
+    for bot in bots {
+        if let Bot::Signal(bot) = bot {
+            if interval_ms(&bot.interval).is_some_and(|interval| interval > length)
+                && !intervals.contains(&bot.interval)
+            {
+                intervals.push(bot.interval.to_string());
+            }
+        }
+    }
+
+    Candles::new(&source, &intervals)
+}
+
+// Replay `klines` (sorted by close time) through `bots`, positions still open at the end are
+// closed at the last price. Signal bots on a longer interval read candles aggregated from them.
+pub fn run(bots: &mut [Bot], klines: &[Kline]) -> Vec<BotReport> {
+    let mut trades: HashMap<String, Vec<TradeRecord>> = HashMap::new();
+    let mut candles = aggregated_candles(bots, klines);
+
+    for kline in klines {
+        let mut intents = Vec::new();
+
+        for (price, time) in intrabar_ticks(kline) {
+            for bot in bots.iter_mut().filter(|bot| bot.symbol == kline.symbol) {
+                let name = bot.name.to_string();
+                let emitted = bot.strategy_mut().on_tick(price, time);
+
+                intents.extend(emitted.into_iter().map(|intent| (name.to_string(), intent)));
+            }
+        }
+
+        intents.extend(strategy::dispatch(
+            bots.iter_mut().map(|bot| bot.strategy_mut()),
+            kline,
+            kline.close_time,
+        ));
+
+        for candle in candles.push(kline) {
+            intents.extend(strategy::dispatch_candle(
+                bots.iter_mut().map(|bot| bot.strategy_mut()),
+                &candle,
+                kline.close_time,
+            ));
+        }
+
+        for (name, intent) in intents {
+            if let Some(trade) = intent.trade() {
+                trades.entry(name).or_default().push(trade);
+            }
+        }
+    }
+
+    let start = klines.first().map(|kline| kline.open_time).unwrap_or_default();
+    let end = klines.last().map(|kline| kline.close_time).unwrap_or_default();
+
+    bots.iter_mut()
+        .map(|bot| {
+            let price = bot.last_price;
+            let mut bot_trades = trades.remove(&bot.name).unwrap_or_default();
+            bot_trades.extend(bot.close_at(price, end, ExitReason::Manual));
+
+            let metrics = Metrics::new(&bot_trades, bot.account.starting_balance, start, end);
+
+            BotReport {
+                name: bot.name.to_string(),
+                family: bot.family.to_string(),
+                summary: Summary::new(&metrics, bot.account.max_drawdown, bot.account.balance),
+                metrics,
+                trades: bot_trades,
+            }
+        })
+        .collect()
+}
+
+pub fn print_reports(reports: &[BotReport]) {
+    let rows: Vec<(String, Metrics)> = reports
+        .iter()
+        .map(|report| (report.name.to_string(), report.metrics.clone()))
+        .collect();
+
+    metrics::print_table(&rows);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BacktestArgs {
+    pub fleet: String,
+    pub symbol: String,
+    pub interval: String,
+    // redis-cli CSV export of the klines, read from redis when missing.
+    pub csv: Option<String>,
+    // Binance dump files or directories, instead of the CSV export.
+    pub dumps: Vec<String>,
+    // indicator key => CSV export, other signal keys are read from redis.
+    pub indicators: HashMap<String, String>,
+    pub from: i64,
+    pub to: i64,
+    // JSON file receiving every bot report with its trades.
+    pub trades: Option<String>,
+}
+
+impl BacktestArgs {
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut parsed = Self {
+            fleet: fleet::DEFAULT_FLEET_PATH.to_string(),
+            symbol: "BTCUSDT".to_string(),
+            interval: "1s".to_string(),
+            csv: None,
+            dumps: Vec::new(),
+            indicators: HashMap::new(),
+            from: 0,
+            to: i64::MAX,
+            trades: None,
+        };
+
+        let mut args = args.iter();
+
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("{} needs a value", flag))?
+                .to_string();
+
+            match flag.as_str() {
+                "--fleet" => parsed.fleet = value,
+                "--symbol" => parsed.symbol = value.to_uppercase(),
+                "--interval" => parsed.interval = value,
+                "--csv" => parsed.csv = Some(value),
+                "--dump" => parsed.dumps.push(value),
+                "--indicators" => {
+                    let (key, path) = value
+                        .split_once('=')
+                        .ok_or_else(|| format!("--indicators expects key=path, got {}", value))?;
+                    parsed.indicators.insert(key.to_string(), path.to_string());
+                }
+                "--from" => parsed.from = value.parse().map_err(|e| format!("--from: {}", e))?,
+                "--to" => parsed.to = value.parse().map_err(|e| format!("--to: {}", e))?,
+                "--trades" => parsed.trades = Some(value),
+                _ => return Err(format!("unknown option {}", flag)),
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    // Klines of the CSV export, the dumps or redis, between `from` and `to`.
+    pub fn klines(&self) -> Result<Vec<Kline>, Box<dyn std::error::Error>> {
+        let mut klines = match &self.csv {
+            Some(path) => load_csv_klines(path, &self.symbol, &self.interval)?,
+            None if !self.dumps.is_empty() => {
+                let files = dumps::dump_files(&self.dumps)?;
+                dumps::dump_stream(&files, Some(&self.symbol), Some(&self.interval))?;
+
+                dumps::load_dumps(&files)?
+                    .iter()
+                    .filter_map(|data| Kline::from_data(&self.symbol, &self.interval, data))
+                    .collect()
+            }
+            None => {
+                let mut con = redis::Client::open(REDIS_URL)?.get_connection()?;
+                load_redis_klines(&mut con, &self.symbol, &self.interval, self.from, self.to)?
+            }
+        };
+
+        klines.retain(|kline| kline.close_time >= self.from && kline.close_time <= self.to);
+
+        Ok(klines)
+    }
+
+    // Indicator points of a signal key, from its `--indicators` CSV export or redis.
+    pub fn indicator_history(&self, key: &str) -> Result<Vec<IndicatorData>, Box<dyn std::error::Error>> {
+        match self.indicators.get(key) {
+            Some(path) => load_csv_indicators(path),
+            None => {
+                let mut con = redis::Client::open(REDIS_URL)?.get_connection()?;
+                load_redis_indicators(&mut con, key)
+            }
+        }
+    }
+}
+
+pub fn run_cli(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let args = BacktestArgs::parse(args)?;
+    let config = fleet::load_fleet_config(&args.fleet)?;
+    let klines = args.klines()?;
+
+    let mut indicators = HashMap::new();
+
+    for signal in config.bots.iter().filter_map(|bot| bot.signal.as_ref()) {
+        if indicators.contains_key(&signal.key) {
+            continue;
+        }
+
+        indicators.insert(signal.key.to_string(), args.indicator_history(&signal.key)?);
+    }
+
+    let mut bots = build_bots(&config, &args.symbol, &indicators)?;
+
+    println!(
+        "VHAKM: Backtesting {} bots on {} {} klines of {}",
+        bots.len(),
+        klines.len(),
+        args.interval,
+        args.symbol
+    );
+
+    let reports = run(&mut bots, &klines);
+    print_reports(&reports);
+
+    if let Some(path) = &args.trades {
+        std::fs::write(path, serde_json::to_string_pretty(&reports)?)?;
+        println!("VHAKM: Trades written to {}", path);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FLEET: &str = r#"
+        [[bots]]
+        name = "test:BTCUSDT:A"
+        family = "vhakm"
+        symbol = "BTCUSDT"
+        take_profit_percentage = 0.03
+        stop_loss_usd = 5.0
+        watch_movement_percentage = 4.0
+
+        [[bots]]
+        name = "test:ETHUSDT:A"
+        family = "vhakm"
+        symbol = "ETHUSDT"
+        take_profit_percentage = 0.03
+        stop_loss_usd = 5.0
+        watch_movement_percentage = 4.0
+    "#;
+
+    fn fleet() -> FleetConfig {
+        config::Config::builder()
+            .add_source(config::File::from_str(FLEET, config::FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap()
+    }
+
+    fn klines(closes: &[f64]) -> Vec<Kline> {
+        closes
+            .iter()
+            .enumerate()
+            .map(|(second, close)| {
+                let data = KlineData {
+                    open: close.to_string(),
+                    high: close.to_string(),
+                    low: close.to_string(),
+                    close: close.to_string(),
+                    volume: "1.0".to_string(),
+                    close_time: second as i64 * 1_000 + 999,
+                };
+
+                Kline::from_data("BTCUSDT", "1s", &data).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_backtest_is_deterministic() {
+        let klines = klines(&[100_000.0, 100_010.0, 100_042.0, 100_042.0, 100_080.0, 100_000.0, 99_950.0, 99_940.0]);
+
+        let mut reports = Vec::new();
+
+        for _ in 0..2 {
+            let mut bots = build_bots(&fleet(), "BTCUSDT", &HashMap::new()).unwrap();
+            assert_eq!(bots.len(), 1);
+
+            reports.push(run(&mut bots, &klines));
+        }
+
+        let report = &reports[0][0];
+        assert_eq!(report.trades.len(), 2);
+        assert_eq!(report.trades[0].exit_reason, ExitReason::TakeProfit);
+        assert_eq!(report.trades[0].gross_pnl, 38.0);
+        assert_eq!(report.trades[1].exit_reason, ExitReason::Manual);
+        assert_eq!(report.trades[1].exit_time, 7_999);
+        assert_eq!(report.trades[1].gross_pnl, 10.0);
+        assert_eq!(report.summary.trades, 2);
+        assert_eq!(report.summary.net_pnl, 48.0);
+
+        assert_eq!(serde_json::to_string(&reports[0]).unwrap(), serde_json::to_string(&reports[1]).unwrap());
+    }
+
+    #[test]
+    fn test_csv_export() {
+        let path = std::env::temp_dir().join("eddie_backtest_klines.csv");
+
+        let lines = [
+            r#""{""open"":""100.0"",""high"":""101.0"",""low"":""99.0"",""close"":""100.5"",""volume"":""2.0"",""close_time"":119999}""#,
+            r#""{""open"":""100.5"",""high"":""102.0"",""low"":""100.0"",""close"":""101.5"",""volume"":""3.0"",""close_time"":59999}""#,
+        ];
+        std::fs::write(&path, lines.join("\n")).unwrap();
+
+        let klines = load_csv_klines(path.to_str().unwrap(), "BTCUSDT", "1m").unwrap();
+
+        assert_eq!(klines.len(), 2);
+        assert_eq!(klines[0].open_time, 0);
+        assert_eq!(klines[0].close, 101.5);
+        assert_eq!(klines[1].high, 101.0);
+
+        // a bullish bar goes down to its low before its high.
+        assert_eq!(
+            intrabar_ticks(&klines[1]),
+            vec![(100.0, 60_000), (99.0, 80_000), (101.0, 100_000)]
+        );
+    }
+
+    #[test]
+    fn test_history_signals_do_not_look_ahead() {
+        let point = |timestamp: i64, ma: f64| {
+            serde_json::from_str::<IndicatorData>(&format!(
+                r#"{{"ma": {}, "ema": 0.0, "pma": 0.0, "rsi": [], "macd": [], "ml1": {{"hold": 0, "buy": 0, "sell": 0}}, "ml_volume": {{"hold": 0, "buy": 0, "sell": 0}}, "bollinger_bands": [], "timestamp": {}}}"#,
+                ma, timestamp
+            ))
+            .unwrap()
+        };
+
+        let config = crate::utils::strategy_builder::strategy_builder::load_strategy_config(
+            r#"{"indicators": {"ma": {"period": 1, "lookback": 2, "threshold": 0.001, "weight": 3.0}}}"#,
+        )
+        .unwrap();
+
+        // seconds: the ma jumps 1% at 3s, 10% at 5s.
+        let history = vec![point(1, 100.0), point(3, 101.0), point(5, 111.1)];
+        let mut signals = HistorySignals::new(config.clone(), history.clone(), 120_000);
+        let kline = |close_time: i64| Kline {
+            close_time,
+            ..klines(&[100.0]).remove(0)
+        };
+
+        assert_eq!(signals.signal(&kline(1_999)).unwrap(), Signal::HOLD);
+        assert_eq!(signals.signal(&kline(3_999)).unwrap(), Signal::BUY);
+        assert_eq!(signals.visible, 2);
+
+        // like live, a point older than max_age_ms at the close holds.
+        let mut signals = HistorySignals::new(config, history, 1_000);
+        assert_eq!(signals.signal(&kline(4_001)).unwrap(), Signal::HOLD);
+        assert_eq!(signals.signal(&kline(5_999)).unwrap(), Signal::BUY);
+        assert_eq!(signals.signal(&kline(6_001)).unwrap(), Signal::HOLD);
+    }
+
+    // Records the candles a signal bot is asked about.
+    struct RecordedSignals(std::rc::Rc<std::cell::RefCell<Vec<(String, i64)>>>);
+
+    impl SignalSource for RecordedSignals {
+        fn signal(&mut self, kline: &Kline) -> Result<Signal, Box<dyn std::error::Error>> {
+            self.0.borrow_mut().push((kline.interval.to_string(), kline.close_time));
+            Ok(Signal::HOLD)
+        }
+    }
+
+    #[test]
+    fn test_signal_bots_read_aggregated_candles() {
+        let candles = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let mut trader = fleet().bots[0].build();
+        trader.persist = false;
+
+        let source = RecordedSignals(candles.clone());
+        let mut bots = [Bot::Signal(SignalTrading::new(trader, "1m", Box::new(source)))];
+
+        run(&mut bots, &klines(&[100_000.0; 150]));
+
+        assert_eq!(
+            *candles.borrow(),
+            vec![("1m".to_string(), 59_999), ("1m".to_string(), 119_999)]
+        );
+    }
+}
@@ -0,0 +1,167 @@
+// Local candle aggregation: closed klines of a short interval (the 1s stream live, the replayed
+// interval in backtests) are merged into candles of longer intervals aligned on UTC boundaries,
+// for timeframes not subscribed from the exchange. A candle is emitted, closed, with the kline
+// ending its period. When klines are missing at the end of a period the candle is emitted as soon
+// as a kline of the next period arrives.
+
+use crate::strategy::{interval_ms, Kline};
+use std::collections::HashMap;
+
+pub struct CandleAggregator {
+    pub interval: String,
+    length: i64,
+    current: Option<Kline>,
+}
+
+impl CandleAggregator {
+    pub fn new(interval: &str) -> Option<Self> {
+        Some(Self {
+            interval: interval.to_string(),
+            length: interval_ms(interval).filter(|length| *length > 0)?,
+            current: None,
+        })
+    }
+
+    // Add a closed kline, returns the candles it completed.
+    pub fn push(&mut self, kline: &Kline) -> Vec<Kline> {
+        let mut closed = Vec::new();
+
+        if !kline.closed {
+            return closed;
+        }
+
+        let start = kline.open_time - kline.open_time.rem_euclid(self.length);
+        let end = start + self.length - 1;
+
+        if let Some(current) = self.current.take() {
+            if current.open_time == start {
+                self.current = Some(Kline {
+                    high: current.high.max(kline.high),
+                    low: current.low.min(kline.low),
+                    close: kline.close,
+                    volume: current.volume + kline.volume,
+                    ..current
+                });
+            } else {
+                closed.push(Kline {
+                    closed: true,
+                    ..current
+                });
+            }
+        }
+
+        let current = self.current.get_or_insert_with(|| Kline {
+            interval: self.interval.to_string(),
+            open_time: start,
+            close_time: end,
+            closed: false,
+            ..kline.clone()
+        });
+
+        if kline.close_time >= end {
+            closed.push(Kline {
+                closed: true,
+                ..current.clone()
+            });
+            self.current = None;
+        }
+
+        closed
+    }
+}
+
+// Aggregators of every symbol, fed with the klines of `source`.
+pub struct Candles {
+    source: String,
+    intervals: Vec<String>,
+    aggregators: HashMap<String, Vec<CandleAggregator>>,
+}
+
+impl Candles {
+    pub fn new(source: &str, intervals: &[String]) -> Self {
+        Self {
+            source: source.to_string(),
+            intervals: intervals.to_vec(),
+            aggregators: HashMap::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    // Candles completed by `kline`, shortest interval first.
+    pub fn push(&mut self, kline: &Kline) -> Vec<Kline> {
+        if kline.interval != self.source {
+            return Vec::new();
+        }
+
+        let intervals = &self.intervals;
+
+        self.aggregators
+            .entry(kline.symbol.to_string())
+            .or_insert_with(|| {
+                intervals
+                    .iter()
+                    .filter_map(|interval| CandleAggregator::new(interval))
+                    .collect()
+            })
+            .iter_mut()
+            .flat_map(|aggregator| aggregator.push(kline))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn second(symbol: &str, time: i64, close: f64) -> Kline {
+        Kline {
+            symbol: symbol.to_string(),
+            interval: "1s".to_string(),
+            open_time: time,
+            close_time: time + 999,
+            open: close - 1.0,
+            high: close + 2.0,
+            low: close - 2.0,
+            close,
+            volume: 1.0,
+            closed: true,
+        }
+    }
+
+    #[test]
+    fn test_aggregate_minutes() {
+        let mut candles = Candles::new("1s", &["1m".to_string(), "5m".to_string()]);
+        let mut closed = Vec::new();
+
+        // 00:00:00 to 00:01:59, then a gap up to 00:05:00.
+        for index in 0..120 {
+            closed.extend(candles.push(&second("BTCUSDT", index * 1_000, 100.0 + index as f64)));
+        }
+
+        assert_eq!(closed.len(), 2);
+        assert_eq!(closed[0].interval, "1m");
+        assert_eq!((closed[0].open_time, closed[0].close_time), (0, 59_999));
+        assert_eq!(closed[0].open, 99.0);
+        assert_eq!(closed[0].high, 161.0);
+        assert_eq!(closed[0].low, 98.0);
+        assert_eq!(closed[0].close, 159.0);
+        assert_eq!(closed[0].volume, 60.0);
+        assert!(closed[0].closed);
+        assert_eq!(closed[1].open, 159.0);
+
+        // the 5m candle of the gap closes with the first kline after it.
+        let closed = candles.push(&second("BTCUSDT", 300_000, 500.0));
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].interval, "5m");
+        assert_eq!((closed[0].close, closed[0].volume), (219.0, 120.0));
+
+        // other symbols and intervals have their own candles.
+        assert!(candles.push(&second("ETHUSDT", 300_000, 3_000.0)).is_empty());
+        let mut minute = second("BTCUSDT", 301_000, 1.0);
+        minute.interval = "1m".to_string();
+        assert!(candles.push(&minute).is_empty());
+    }
+}
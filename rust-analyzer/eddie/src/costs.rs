@@ -0,0 +1,207 @@
+// Trading costs applied to every simulated trade: exchange fees, slippage and funding.
+// Entries and stop loss / manual exits are market orders (taker, with slippage),
+// take profits are resting limit orders (maker, filled at the limit price).
+
+use crate::ledger::{ExitReason, Side};
+use serde::{Deserialize, Serialize};
+
+const FUNDING_INTERVAL_MS: f64 = 8.0 * 60.0 * 60.0 * 1000.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct FeeSchedule {
+    // Percentages of the traded notional, 0.02 = 0.02%.
+    pub maker_percentage: f64,
+    pub taker_percentage: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "model", content = "value", rename_all = "lowercase")]
+pub enum Slippage {
+    #[default]
+    None,
+    // Price slippage in USD per market fill.
+    Fixed(f64),
+    // Price slippage in percentage of the price per market fill.
+    Percentage(f64),
+}
+
+impl Slippage {
+    pub fn per_fill(&self, price: f64) -> f64 {
+        match self {
+            Slippage::None => 0.0,
+            Slippage::Fixed(usd) => *usd,
+            Slippage::Percentage(percentage) => price * percentage / 100.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TradingCosts {
+    #[serde(default)]
+    pub fees: FeeSchedule,
+    #[serde(default)]
+    pub slippage: Slippage,
+    // Paid by longs and received by shorts every 8 hours, pro rata of the holding time.
+    #[serde(default)]
+    pub funding_rate_percentage: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct CostBreakdown {
+    pub fees: f64,
+    pub slippage: f64,
+    pub funding: f64,
+}
+
+impl CostBreakdown {
+    pub fn total(&self) -> f64 {
+        self.fees + self.slippage + self.funding
+    }
+}
+
+impl TradingCosts {
+    pub fn validate(&self) -> Result<(), String> {
+        let slippage = match self.slippage {
+            Slippage::None => 0.0,
+            Slippage::Fixed(value) | Slippage::Percentage(value) => value,
+        };
+
+        for (name, value) in [
+            ("fees.maker_percentage", self.fees.maker_percentage),
+            ("fees.taker_percentage", self.fees.taker_percentage),
+            ("slippage", slippage),
+        ] {
+            if !value.is_finite() || value < 0.0 {
+                return Err(format!("{} must be zero or positive, got {}", name, value));
+            }
+        }
+
+        if !self.funding_rate_percentage.is_finite() {
+            return Err("funding_rate_percentage must be a number".to_string());
+        }
+
+        Ok(())
+    }
+
+    // Costs of a round trip on `quantity` of the base asset, in quote currency.
+    pub fn round_trip(
+        &self,
+        side: Side,
+        entry_price: f64,
+        exit_price: f64,
+        quantity: f64,
+        exit_reason: ExitReason,
+        held_ms: i64,
+    ) -> CostBreakdown {
+        let exit_is_maker = matches!(
+            exit_reason,
+            ExitReason::TakeProfit | ExitReason::PartialTakeProfit
+        );
+
+        let exit_fee_percentage = if exit_is_maker {
+            self.fees.maker_percentage
+        } else {
+            self.fees.taker_percentage
+        };
+
+        let fees = entry_price * self.fees.taker_percentage / 100.0
+            + exit_price * exit_fee_percentage / 100.0;
+
+        let mut slippage = self.slippage.per_fill(entry_price);
+
+        if !exit_is_maker {
+            slippage += self.slippage.per_fill(exit_price);
+        }
+
+        let funding_periods = held_ms.max(0) as f64 / FUNDING_INTERVAL_MS;
+        let funding = entry_price * self.funding_rate_percentage / 100.0 * funding_periods;
+
+        CostBreakdown {
+            fees: fees * quantity,
+            slippage: slippage * quantity,
+            funding: match side {
+                Side::Long => funding * quantity,
+                Side::Short => -funding * quantity,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binance_futures() -> TradingCosts {
+        TradingCosts {
+            fees: FeeSchedule {
+                maker_percentage: 0.02,
+                taker_percentage: 0.05,
+            },
+            slippage: Slippage::Fixed(1.0),
+            funding_rate_percentage: 0.01,
+        }
+    }
+
+    #[test]
+    fn test_scalper_take_profit_is_a_loss() {
+        // ScalperV1 takes profit at 0.015%, that is 15$ on a 100k BTC.
+        let costs = binance_futures().round_trip(
+            Side::Long,
+            100_000.0,
+            100_015.0,
+            1.0,
+            ExitReason::TakeProfit,
+            0,
+        );
+
+        assert_eq!(format!("{:.3}", costs.fees), "70.003");
+        assert_eq!(costs.slippage, 1.0);
+        assert_eq!(costs.funding, 0.0);
+        assert!(15.0 - costs.total() < 0.0);
+    }
+
+    #[test]
+    fn test_stop_loss_slippage_and_funding() {
+        let costs = binance_futures().round_trip(
+            Side::Short,
+            100_000.0,
+            100_010.0,
+            1.0,
+            ExitReason::StopLoss,
+            4 * 60 * 60 * 1000,
+        );
+
+        assert_eq!(format!("{:.3}", costs.fees), "100.005");
+        assert_eq!(costs.slippage, 2.0);
+        assert_eq!(format!("{:.3}", costs.funding), "-5.000");
+    }
+
+    #[test]
+    fn test_default_is_free() {
+        let costs = TradingCosts::default().round_trip(
+            Side::Long,
+            100_000.0,
+            99_000.0,
+            1.0,
+            ExitReason::Manual,
+            1_000,
+        );
+
+        assert_eq!(costs.total(), 0.0);
+    }
+
+    #[test]
+    fn test_costs_scale_with_quantity() {
+        let costs = binance_futures().round_trip(
+            Side::Long,
+            100_000.0,
+            99_990.0,
+            0.002,
+            ExitReason::StopLoss,
+            0,
+        );
+
+        assert_eq!(format!("{:.4}", costs.fees), "0.2000");
+        assert_eq!(format!("{:.4}", costs.slippage), "0.0040");
+    }
+}
@@ -0,0 +1,346 @@
+// Binance public kline dumps (data.binance.vision): monthly or daily zipped CSV files named
+// {SYMBOL}-{interval}-{YYYY-MM[-DD]}.zip with the columns open time, open, high, low, close,
+// volume, close time, quote volume, trades, taker buy base volume, taker buy quote volume, ignore.
+// Rows are converted to the KlineData stored by Hooks::kline and imported into the
+// klines:{symbol}:{interval} sorted sets, or into a local CSV file that backtests read directly.
+//
+//   eddie import BTCUSDT-1m-2024-01.zip BTCUSDT-1m-2024-02.zip --output klines_btc_usdt_1m.csv
+
+use crate::backtest::klines_key;
+use crate::processors::binance_hooks::binance_hooks::KlineData;
+use crate::simple_trading::REDIS_URL;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+// Members per ZADD when importing into redis.
+const REDIS_BATCH: usize = 5_000;
+
+// Symbol and interval of a dump file name, e.g. BTCUSDT-1m-2024-01.zip.
+pub fn dump_name(path: &Path) -> Option<(String, String)> {
+    let stem = path.file_stem()?.to_str()?;
+    let mut parts = stem.split('-');
+
+    Some((parts.next()?.to_uppercase(), parts.next()?.to_string()))
+}
+
+// Dumps since 2025 carry microsecond timestamps, klines are in milliseconds.
+fn time_ms(time: i64) -> i64 {
+    if time > 100_000_000_000_000 {
+        time / 1_000
+    } else {
+        time
+    }
+}
+
+// Kline of one dump row, None for the header line some dumps start with.
+fn parse_row(record: &csv::StringRecord) -> Option<KlineData> {
+    let open_time: i64 = record.get(0)?.trim().parse().ok()?;
+    let close_time: i64 = record.get(6)?.trim().parse().ok()?;
+
+    if open_time <= 0 {
+        return None;
+    }
+
+    Some(KlineData {
+        open: record.get(1)?.trim().to_string(),
+        high: record.get(2)?.trim().to_string(),
+        low: record.get(3)?.trim().to_string(),
+        close: record.get(4)?.trim().to_string(),
+        volume: record.get(5)?.trim().to_string(),
+        close_time: time_ms(close_time),
+    })
+}
+
+fn read_rows(reader: impl Read) -> Result<Vec<KlineData>, Box<dyn std::error::Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(reader);
+
+    let mut klines = Vec::new();
+
+    for record in reader.records() {
+        if let Some(kline) = parse_row(&record?) {
+            klines.push(kline);
+        }
+    }
+
+    Ok(klines)
+}
+
+// Klines of a dump, zipped or already extracted.
+pub fn load_dump(path: &Path) -> Result<Vec<KlineData>, Box<dyn std::error::Error>> {
+    let file = File::open(path)?;
+
+    if path.extension().and_then(|extension| extension.to_str()) != Some("zip") {
+        return read_rows(file);
+    }
+
+    let mut archive = zip::ZipArchive::new(file)?;
+    let mut klines = Vec::new();
+
+    for index in 0..archive.len() {
+        let entry = archive.by_index(index)?;
+
+        if entry.name().ends_with(".csv") {
+            klines.extend(read_rows(entry)?);
+        }
+    }
+
+    Ok(klines)
+}
+
+// Dump files of `paths`, directories are expanded to the .zip/.csv files they contain.
+pub fn dump_files(paths: &[String]) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let mut files = Vec::new();
+
+    for path in paths.iter().map(PathBuf::from) {
+        if path.is_dir() {
+            for entry in std::fs::read_dir(&path)? {
+                let file = entry?.path();
+
+                if matches!(file.extension().and_then(|e| e.to_str()), Some("zip" | "csv")) {
+                    files.push(file);
+                }
+            }
+        } else {
+            files.push(path);
+        }
+    }
+
+    files.sort();
+
+    Ok(files)
+}
+
+// Symbol and interval shared by every file, `symbol` and `interval` when given. Files with another
+// symbol or interval in their name are rejected rather than merged into one key, names that do
+// not parse are only accepted when both are given.
+pub fn dump_stream(
+    files: &[PathBuf],
+    symbol: Option<&str>,
+    interval: Option<&str>,
+) -> Result<(String, String), String> {
+    if files.is_empty() {
+        return Err("no dump files given".to_string());
+    }
+
+    let mut stream = symbol.zip(interval).map(|(s, i)| (s.to_uppercase(), i.to_string()));
+
+    for file in files {
+        let Some((file_symbol, file_interval)) = dump_name(file) else {
+            if symbol.is_some() && interval.is_some() {
+                continue;
+            }
+
+            return Err(format!(
+                "{}: not a {{SYMBOL}}-{{interval}}-{{date}} dump name, pass --symbol and --interval",
+                file.display()
+            ));
+        };
+
+        let expected = stream.get_or_insert_with(|| {
+            (
+                symbol.map(str::to_uppercase).unwrap_or(file_symbol.to_string()),
+                interval.map(str::to_string).unwrap_or(file_interval.to_string()),
+            )
+        });
+
+        if (&file_symbol, &file_interval) != (&expected.0, &expected.1) {
+            return Err(format!(
+                "{} holds {} {} klines, not {} {}",
+                file.display(),
+                file_symbol,
+                file_interval,
+                expected.0,
+                expected.1
+            ));
+        }
+    }
+
+    stream.ok_or_else(|| "no dump files given".to_string())
+}
+
+// Every kline of `files`, ordered by close time without duplicates (overlapping dumps).
+pub fn load_dumps(files: &[PathBuf]) -> Result<Vec<KlineData>, Box<dyn std::error::Error>> {
+    let mut klines = Vec::new();
+
+    for file in files {
+        klines.extend(load_dump(file).map_err(|e| format!("{}: {}", file.display(), e))?);
+    }
+
+    klines.sort_by_key(|kline| kline.close_time);
+    klines.dedup_by_key(|kline| kline.close_time);
+
+    Ok(klines)
+}
+
+pub fn import_redis(
+    con: &mut redis::Connection,
+    symbol: &str,
+    interval: &str,
+    klines: &[KlineData],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let key = klines_key(symbol, interval);
+
+    for batch in klines.chunks(REDIS_BATCH) {
+        let mut pipe = redis::pipe();
+
+        for kline in batch {
+            pipe.zadd(&key, serde_json::to_string(kline)?, kline.close_time)
+                .ignore();
+        }
+
+        pipe.query::<()>(con)?;
+    }
+
+    Ok(())
+}
+
+// Local store: the same lines as `redis-cli --csv ZRANGE klines:{symbol}:{interval} 0 -1`.
+pub fn write_csv(path: &str, klines: &[KlineData]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut writer = csv::WriterBuilder::new()
+        .quote_style(csv::QuoteStyle::Always)
+        .from_path(path)?;
+
+    for kline in klines {
+        writer.write_record([serde_json::to_string(kline)?])?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+pub fn run_cli(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut paths = Vec::new();
+    let mut symbol = None;
+    let mut interval = None;
+    let mut output = None;
+
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("{} needs a value", arg))
+        };
+
+        match arg.as_str() {
+            "--symbol" => symbol = Some(value()?.to_uppercase()),
+            "--interval" => interval = Some(value()?),
+            "--output" => output = Some(value()?),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg).into()),
+            _ => paths.push(arg.to_string()),
+        }
+    }
+
+    let files = dump_files(&paths)?;
+    let (symbol, interval) = dump_stream(&files, symbol.as_deref(), interval.as_deref())?;
+    let klines = load_dumps(&files)?;
+
+    match &output {
+        Some(path) => write_csv(path, &klines)?,
+        None => {
+            let mut con = redis::Client::open(REDIS_URL)?.get_connection()?;
+            import_redis(&mut con, &symbol, &interval, &klines)?;
+        }
+    }
+
+    println!(
+        "VHAKM: Imported {} {} klines of {} from {} files into {}",
+        klines.len(),
+        interval,
+        symbol,
+        files.len(),
+        output.unwrap_or(klines_key(&symbol, &interval))
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const ROWS: &str = "open_time,open,high,low,close,volume,close_time,quote_volume,count,taker_buy_volume,taker_buy_quote_volume,ignore
+1704067260000,42314.00,42335.80,42300.00,42330.10,120.5,1704067319999,5100000.0,1500,60.1,2540000.0,0
+1704067200000,42283.58,42320.00,42280.10,42314.00,98.2,1704067259999,4150000.0,1320,50.3,2120000.0,0
+";
+
+    #[test]
+    fn test_load_zipped_dump() {
+        let path = std::env::temp_dir().join("BTCUSDT-1m-2024-01.zip");
+
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        zip.start_file("BTCUSDT-1m-2024-01.csv", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(ROWS.as_bytes()).unwrap();
+        zip.finish().unwrap();
+
+        assert_eq!(dump_name(&path), Some(("BTCUSDT".to_string(), "1m".to_string())));
+
+        let klines = load_dumps(&[path.clone(), path]).unwrap();
+
+        assert_eq!(klines.len(), 2);
+        assert_eq!(klines[0].open, "42283.58");
+        assert_eq!(klines[0].close_time, 1704067259999);
+        assert_eq!(klines[1].volume, "120.5");
+    }
+
+    #[test]
+    fn test_mismatched_dumps() {
+        // a directory mixing two symbols is not merged into one key.
+        let dir = std::env::temp_dir().join("eddie_mixed_dumps");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir(&dir).unwrap();
+
+        for name in ["BTCUSDT-1m-2024-01.csv", "ETHUSDT-1m-2024-01.csv"] {
+            std::fs::write(dir.join(name), ROWS).unwrap();
+        }
+
+        let output = std::env::temp_dir().join("eddie_mixed_klines.csv");
+        let _ = std::fs::remove_file(&output);
+        let args = [dir.to_str().unwrap(), "--output", output.to_str().unwrap()];
+
+        let error = run_cli(&args.map(str::to_string)).unwrap_err().to_string();
+        assert!(error.contains("holds ETHUSDT 1m klines, not BTCUSDT 1m"), "{}", error);
+        assert!(!output.exists());
+
+        let btc = [dir.join("BTCUSDT-1m-2024-01.csv")];
+        let stream = |symbol: &str, interval: &str| Ok((symbol.to_string(), interval.to_string()));
+
+        assert_eq!(dump_stream(&btc, None, None), stream("BTCUSDT", "1m"));
+        assert!(dump_stream(&btc, Some("ethusdt"), None).is_err());
+        assert!(dump_stream(&btc, None, Some("5m")).is_err());
+        assert!(dump_stream(&[], None, None).is_err());
+
+        // names that do not parse need both the symbol and the interval.
+        let export = [std::env::temp_dir().join("klines_btc_usdt_1m.csv")];
+        assert!(dump_stream(&export, Some("BTCUSDT"), None).is_err());
+        assert_eq!(dump_stream(&export, Some("btcusdt"), Some("1m")), stream("BTCUSDT", "1m"));
+    }
+
+    #[test]
+    fn test_microsecond_dump_to_csv_store() {
+        let dump = std::env::temp_dir().join("BTCUSDT-1s-2025-01-01.csv");
+        std::fs::write(
+            &dump,
+            "1735689600000000,93576.00,93610.93,93537.50,93610.93,8.2,1735689600999999,768000.0,90,4.1,384000.0,0\n",
+        )
+        .unwrap();
+
+        let klines = load_dumps(&[dump]).unwrap();
+        assert_eq!(klines[0].close_time, 1735689600999);
+
+        let store = std::env::temp_dir().join("eddie_klines_btc_usdt_1s.csv");
+        write_csv(store.to_str().unwrap(), &klines).unwrap();
+
+        let loaded = crate::backtest::load_csv_klines(store.to_str().unwrap(), "BTCUSDT", "1s").unwrap();
+        assert_eq!(loaded[0].close, 93610.93);
+        assert_eq!(loaded[0].open_time, 1735689600000);
+    }
+}
@@ -0,0 +1,226 @@
+// Optional exit rules of a bot on top of its fixed stop loss and take profit:
+// a trailing stop, a move of the stop to break-even and partial take profits.
+// Stops only ever move in favour of the position.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum TrailingStop {
+    // USD given back by the whole position from its best price.
+    Usd { value: f64 },
+    // Percentage of the best price.
+    Percentage { value: f64 },
+    // Multiple of the average true range of the ticks seen by the bot.
+    Atr { multiple: f64, period: usize },
+}
+
+impl TrailingStop {
+    // Price distance between the best price and the stop, None until the ATR is warmed up.
+    pub fn distance(&self, best_price: f64, quantity: f64, atr: &AverageTrueRange) -> Option<f64> {
+        match self {
+            TrailingStop::Usd { value } => Some(value / quantity),
+            TrailingStop::Percentage { value } => Some(best_price * value / 100.0),
+            TrailingStop::Atr { multiple, .. } => atr.value().map(|atr| atr * multiple),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BreakEven {
+    // Favourable move from the entry, in percent of the entry price, that moves the stop.
+    pub trigger_percentage: f64,
+    // Where the stop goes past the entry, e.g. to cover the fees.
+    #[serde(default)]
+    pub offset_percentage: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PartialTakeProfit {
+    // Favourable move from the entry, in percent of the entry price.
+    pub percentage: f64,
+    // Fraction of the initial quantity closed at this level.
+    pub fraction: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExitRules {
+    #[serde(default)]
+    pub trailing_stop: Option<TrailingStop>,
+    #[serde(default)]
+    pub break_even: Option<BreakEven>,
+    #[serde(default)]
+    pub partial_take_profits: Vec<PartialTakeProfit>,
+}
+
+impl ExitRules {
+    pub fn validate(&self) -> Result<(), String> {
+        match self.trailing_stop {
+            Some(TrailingStop::Usd { value }) | Some(TrailingStop::Percentage { value })
+                if !value.is_finite() || value <= 0.0 =>
+            {
+                return Err(format!("trailing_stop value must be positive, got {}", value));
+            }
+            Some(TrailingStop::Atr { multiple, period })
+                if !multiple.is_finite() || multiple <= 0.0 || period == 0 =>
+            {
+                return Err("trailing_stop atr needs a positive multiple and period".to_string());
+            }
+            _ => {}
+        }
+
+        if let Some(break_even) = self.break_even {
+            if !break_even.trigger_percentage.is_finite() || break_even.trigger_percentage <= 0.0 {
+                return Err("break_even trigger_percentage must be positive".to_string());
+            }
+
+            let offset = break_even.offset_percentage;
+
+            if !offset.is_finite() || offset < 0.0 {
+                return Err(format!("break_even offset_percentage must be 0 or more, got {}", offset));
+            }
+
+            if offset >= break_even.trigger_percentage {
+                return Err("break_even offset_percentage must be below trigger_percentage".to_string());
+            }
+        }
+
+        let mut fractions = 0.0;
+
+        for partial in &self.partial_take_profits {
+            if !partial.percentage.is_finite() || partial.percentage <= 0.0 {
+                return Err("partial_take_profits percentage must be positive".to_string());
+            }
+
+            if !(partial.fraction > 0.0 && partial.fraction < 1.0) {
+                return Err("partial_take_profits fraction must be in ]0, 1[".to_string());
+            }
+
+            fractions += partial.fraction;
+        }
+
+        if fractions >= 1.0 {
+            return Err("partial_take_profits fractions must add up to less than 1".to_string());
+        }
+
+        Ok(())
+    }
+
+    pub fn atr_period(&self) -> usize {
+        match self.trailing_stop {
+            Some(TrailingStop::Atr { period, .. }) => period,
+            _ => DEFAULT_ATR_PERIOD,
+        }
+    }
+
+    // Partial take profits ordered from the closest to the furthest level.
+    pub fn sorted_partials(&self) -> Vec<PartialTakeProfit> {
+        let mut partials = self.partial_take_profits.clone();
+        partials.sort_by(|a, b| a.percentage.total_cmp(&b.percentage));
+        partials
+    }
+}
+
+pub const DEFAULT_ATR_PERIOD: usize = 14;
+
+// Wilder average of the absolute tick to tick price changes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AverageTrueRange {
+    pub period: usize,
+    average: f64,
+    samples: usize,
+}
+
+impl AverageTrueRange {
+    pub fn new(period: usize) -> Self {
+        Self {
+            period: period.max(1),
+            average: 0.0,
+            samples: 0,
+        }
+    }
+
+    pub fn update(&mut self, true_range: f64) {
+        self.samples += 1;
+
+        if self.samples <= self.period {
+            self.average += (true_range - self.average) / self.samples as f64;
+        } else {
+            self.average += (true_range - self.average) / self.period as f64;
+        }
+    }
+
+    pub fn value(&self) -> Option<f64> {
+        (self.samples >= self.period).then_some(self.average)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_average_true_range() {
+        let mut atr = AverageTrueRange::new(3);
+
+        atr.update(2.0);
+        atr.update(4.0);
+        assert_eq!(atr.value(), None);
+
+        atr.update(6.0);
+        assert_eq!(atr.value(), Some(4.0));
+
+        atr.update(7.0);
+        assert_eq!(atr.value(), Some(5.0));
+
+        let trailing = TrailingStop::Atr {
+            multiple: 2.0,
+            period: 3,
+        };
+        assert_eq!(trailing.distance(100.0, 1.0, &atr), Some(10.0));
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut rules = ExitRules {
+            partial_take_profits: vec![
+                PartialTakeProfit {
+                    percentage: 0.05,
+                    fraction: 0.5,
+                },
+                PartialTakeProfit {
+                    percentage: 0.02,
+                    fraction: 0.25,
+                },
+            ],
+            ..ExitRules::default()
+        };
+
+        assert!(rules.validate().is_ok());
+        assert_eq!(rules.sorted_partials()[0].percentage, 0.02);
+
+        rules.partial_take_profits[1].fraction = 0.5;
+        assert!(rules.validate().is_err());
+
+        rules.partial_take_profits.clear();
+        rules.trailing_stop = Some(TrailingStop::Usd { value: 0.0 });
+        assert!(rules.validate().is_err());
+
+        rules.trailing_stop = None;
+        for offset in [0.0, 0.005] {
+            rules.break_even = Some(BreakEven {
+                trigger_percentage: 0.02,
+                offset_percentage: offset,
+            });
+            assert!(rules.validate().is_ok());
+        }
+
+        for offset in [-0.005, f64::NAN, f64::NEG_INFINITY, 0.02] {
+            rules.break_even = Some(BreakEven {
+                trigger_percentage: 0.02,
+                offset_percentage: offset,
+            });
+            assert!(rules.validate().is_err(), "{}", offset);
+        }
+    }
+}
@@ -0,0 +1,184 @@
+// REST calls of the live pipeline: the backfill of a stream gap (up to MAX_BACKFILL klines, a few
+// requests) and the order book snapshots. They run one after the other on a worker thread so the
+// websocket callback never waits for them, the Runner applies their results in request order on
+// the following events (see Runner::event).
+
+use crate::order_book::SNAPSHOT_DEPTH;
+use crate::reconnect;
+use crate::strategy::Kline;
+use binance::market::Market;
+use binance::model;
+use std::ops::Range;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Request {
+    Klines {
+        symbol: String,
+        interval: String,
+        gap: Range<i64>,
+    },
+    Depth {
+        symbol: String,
+    },
+}
+
+pub enum Fetched {
+    Klines {
+        symbol: String,
+        interval: String,
+        result: Result<Vec<Kline>, String>,
+    },
+    Depth {
+        symbol: String,
+        result: Result<model::OrderBook, String>,
+    },
+}
+
+pub struct Fetcher {
+    requests: Sender<Request>,
+    results: Receiver<Fetched>,
+}
+
+impl Fetcher {
+    // The worker stops once the fetcher is dropped.
+    pub fn spawn(mut fetch: impl FnMut(Request) -> Fetched + Send + 'static) -> Self {
+        let (requests, pending) = mpsc::channel::<Request>();
+        let (done, results) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            for request in pending {
+                if done.send(fetch(request)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self { requests, results }
+    }
+
+    pub fn binance(market: Market) -> Self {
+        Self::spawn(move |request| match request {
+            Request::Klines {
+                symbol,
+                interval,
+                gap,
+            } => Fetched::Klines {
+                result: reconnect::fetch(&market, &symbol, &interval, gap),
+                symbol,
+                interval,
+            },
+            Request::Depth { symbol } => Fetched::Depth {
+                result: market
+                    .get_custom_depth(symbol.as_str(), SNAPSHOT_DEPTH)
+                    .map_err(|e| e.to_string()),
+                symbol,
+            },
+        })
+    }
+
+    pub fn request(&self, request: Request) {
+        if self.requests.send(request).is_err() {
+            println!("VHAKM: REST worker stopped");
+        }
+    }
+
+    // The results received so far, oldest first.
+    pub fn fetched(&self) -> Vec<Fetched> {
+        self.results.try_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fleet::{Fleet, FleetConfig};
+    use crate::replay::{self, Entry, Recorder};
+    use crate::runner::Runner;
+    use binance::websockets::WebsocketEvent;
+    use std::collections::BTreeSet;
+    use std::time::Duration;
+
+    fn event(second: i64) -> WebsocketEvent {
+        let payload = serde_json::json!({
+            "e": "kline", "E": second * 1_000 + 1_000, "s": "BTCUSDT",
+            "k": {
+                "t": second * 1_000, "T": second * 1_000 + 999, "s": "BTCUSDT", "i": "1s",
+                "f": 1, "L": 2, "o": "100.0", "c": "100.0", "h": "100.0", "l": "100.0",
+                "v": "1.0", "n": 2, "x": true, "q": "0", "V": "0", "Q": "0", "B": "0"
+            }
+        });
+
+        WebsocketEvent::Kline(serde_json::from_value(payload).unwrap())
+    }
+
+    // 1s klines of the gap, or a failed snapshot.
+    fn fetch(request: Request) -> Fetched {
+        match request {
+            Request::Klines {
+                symbol,
+                interval,
+                gap,
+            } => Fetched::Klines {
+                result: Ok(gap
+                    .step_by(1_000)
+                    .map(|open_time| Kline {
+                        symbol: symbol.to_string(),
+                        interval: interval.to_string(),
+                        open_time,
+                        close_time: open_time + 999,
+                        open: 100.0,
+                        high: 100.0,
+                        low: 100.0,
+                        close: 100.0,
+                        volume: 1.0,
+                        closed: true,
+                    })
+                    .collect()),
+                symbol,
+                interval,
+            },
+            Request::Depth { symbol } => Fetched::Depth {
+                symbol,
+                result: Err("offline".to_string()),
+            },
+        }
+    }
+
+    #[test]
+    fn test_backfill_merged_in_order() {
+        let path = std::env::temp_dir().join("eddie_fetcher_session.jsonl");
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        let symbols = BTreeSet::from(["BTCUSDT".to_string()]);
+        let mut runner = Runner::new(Fleet::new(FleetConfig::default()), symbols)
+            .without_persistence()
+            .with_fetcher(Fetcher::spawn(fetch))
+            .with_recorder(Recorder::open(path).unwrap());
+
+        // seconds 1 and 2 are missed, 3 (and 4 when the worker is slower) waits for the backfill.
+        for second in [0, 3, 4] {
+            runner.event(event(second));
+        }
+
+        std::thread::sleep(Duration::from_millis(100));
+        runner.event(event(5));
+        drop(runner);
+
+        let order: Vec<Vec<i64>> = replay::load(path)
+            .unwrap()
+            .into_iter()
+            .filter_map(|record| match record.entry {
+                Entry::Event(WebsocketEvent::Kline(event)) => Some(vec![event.kline.open_time]),
+                Entry::Backfill(klines) => Some(klines.iter().map(|k| k.open_time).collect()),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(
+            order,
+            vec![vec![0], vec![1_000, 2_000], vec![3_000], vec![4_000], vec![5_000]]
+        );
+    }
+}
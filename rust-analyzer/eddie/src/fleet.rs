@@ -0,0 +1,931 @@
+// Fleet definition: every SimpleTrading bot is declared in a TOML/JSON file (fleet.toml by default)
+// instead of being hardcoded in main.rs. Take profits are written as percentages (0.03 = 0.03%),
+// watch movements in hundredths of a percent (0.04 = 0.0004%).
+// The file is watched while the websocket loop runs, so bots can be added, retired or retuned live.
+
+use crate::account::Account;
+use crate::costs::TradingCosts;
+use crate::exits::{AverageTrueRange, ExitRules};
+use crate::ledger::{ExitReason, TradeRecord};
+use crate::limits::TradeLimits;
+use crate::order_book::DepthConfig;
+use crate::order_flow::OrderFlowConfig;
+use crate::signal_trading::{SignalConfig, SignalTrading};
+use crate::simple_trading::SimpleTrading;
+use crate::strategy::{interval_ms, Strategy, TICK_INTERVAL, TICK_INTERVAL_MS};
+use crate::sizing::{default_leverage, PositionSizing};
+use crate::symbols::SymbolRules;
+use crate::utils::technical_indicators::technical_indicators::VariationConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::time::{Duration, Instant, SystemTime};
+
+pub const DEFAULT_FLEET_PATH: &str = "fleet.toml";
+
+const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+// What happens to the open position of a bot removed from the fleet file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RemovePolicy {
+    // Close the position at the last seen price and event time and book the result.
+    #[default]
+    Close,
+    // Drop the position without booking anything.
+    Abandon,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BotConfig {
+    pub name: String,
+    pub family: String,
+    #[serde(default)]
+    pub symbol: String,
+    // Runs the bot on each of these symbols instead, as "{SYMBOL}:{name}".
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub symbols: Vec<String>,
+    pub take_profit_percentage: f64,
+    pub stop_loss_usd: f64,
+    // Divided by 100 before the comparison with the move from the watch price in percent.
+    pub watch_movement_percentage: f64,
+    // Overrides the fleet wide costs for this bot.
+    #[serde(default)]
+    pub costs: Option<TradingCosts>,
+    // Overrides the tick and lot sizes of the symbol for this bot.
+    #[serde(default)]
+    pub rules: Option<SymbolRules>,
+    #[serde(default)]
+    pub sizing: PositionSizing,
+    #[serde(default = "default_leverage")]
+    pub leverage: f64,
+    // Starting virtual balance in quote currency, realized net P&L is compounded into it.
+    #[serde(default)]
+    pub balance: f64,
+    // Size balance percentage positions from the current balance instead of the starting one.
+    #[serde(default)]
+    pub reinvest: bool,
+    // Trailing stop, break-even and partial take profits, all off by default.
+    #[serde(default)]
+    pub exits: ExitRules,
+    // Maximum holding time, cooldown after a stop loss and daily trade cap, all off by default.
+    #[serde(default)]
+    pub limits: TradeLimits,
+    // Entries from a StrategyConfig evaluated on closed klines instead of the movement watch.
+    #[serde(default)]
+    pub signal: Option<SignalConfig>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FleetConfig {
+    #[serde(default)]
+    pub on_remove: RemovePolicy,
+    #[serde(default)]
+    pub costs: TradingCosts,
+    // Tick and lot sizes per symbol, e.g. [symbols.ETHUSDT].
+    #[serde(default)]
+    pub symbols: BTreeMap<String, SymbolRules>,
+    // Kline streams subscribed for every traded symbol besides the 1s one the bots tick on.
+    #[serde(default)]
+    pub intervals: Vec<String>,
+    // Candles built locally from the 1s klines, for timeframes without an exchange stream.
+    #[serde(default)]
+    pub aggregated_intervals: Vec<String>,
+    // Order book of every traded symbol from the depth stream, off when absent.
+    #[serde(default)]
+    pub depth: Option<DepthConfig>,
+    // Taker buy/sell pressure of every traded symbol from the aggregated trade stream, off when
+    // absent.
+    #[serde(default)]
+    pub order_flow: Option<OrderFlowConfig>,
+    // Retention of the price/volume variations of the 1s klines.
+    #[serde(default)]
+    pub variations: VariationConfig,
+    pub bots: Vec<BotConfig>,
+}
+
+#[derive(Debug)]
+pub struct FleetError {
+    pub index: usize,
+    pub name: String,
+    pub message: String,
+}
+
+impl fmt::Display for FleetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bots[{}] ({:?}): {}", self.index, self.name, self.message)
+    }
+}
+
+impl std::error::Error for FleetError {}
+
+impl BotConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("name must not be empty".to_string());
+        }
+
+        if self.family.trim().is_empty() {
+            return Err("family must not be empty".to_string());
+        }
+
+        if self.symbol.is_empty() || self.symbol != self.symbol.to_uppercase() {
+            return Err(format!("symbol {:?} must be an uppercase pair such as BTCUSDT", self.symbol));
+        }
+
+        if !self.take_profit_percentage.is_finite() || self.take_profit_percentage <= 0.0 {
+            return Err(format!(
+                "take_profit_percentage must be positive, got {}",
+                self.take_profit_percentage
+            ));
+        }
+
+        if !self.stop_loss_usd.is_finite() || self.stop_loss_usd < 0.0 {
+            return Err(format!("stop_loss_usd must be zero or positive, got {}", self.stop_loss_usd));
+        }
+
+        if !self.watch_movement_percentage.is_finite() || self.watch_movement_percentage <= 0.0 {
+            return Err(format!(
+                "watch_movement_percentage must be positive, got {}",
+                self.watch_movement_percentage
+            ));
+        }
+
+        if !self.balance.is_finite() || self.balance < 0.0 {
+            return Err(format!("balance must be zero or positive, got {}", self.balance));
+        }
+
+        self.sizing.validate(self.leverage, self.balance)?;
+        self.exits.validate().map_err(|e| format!("exits: {}", e))?;
+        self.limits.validate().map_err(|e| format!("limits: {}", e))?;
+
+        if let Some(signal) = &self.signal {
+            signal.validate().map_err(|e| format!("signal: {}", e))?;
+        }
+
+        if let Some(costs) = &self.costs {
+            costs.validate().map_err(|e| format!("costs: {}", e))?;
+        }
+
+        if let Some(rules) = &self.rules {
+            rules.validate().map_err(|e| format!("rules: {}", e))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn build(&self) -> SimpleTrading {
+        let mut bot = SimpleTrading::new(
+            self.name.to_string(),
+            0.0,
+            self.take_profit_percentage / 100.0,
+            self.stop_loss_usd,
+            self.watch_movement_percentage,
+        );
+
+        bot.family = self.family.to_string();
+        bot.symbol = self.symbol.to_string();
+        bot.costs = self.costs.unwrap_or_default();
+        bot.rules = self.rules.unwrap_or_default();
+        bot.sizing = self.sizing;
+        bot.leverage = self.leverage;
+        bot.account = Account::new(self.balance, self.reinvest);
+        bot.exits = self.exits.clone();
+        bot.atr = AverageTrueRange::new(self.exits.atr_period());
+        bot.limits = self.limits;
+
+        bot
+    }
+
+    pub fn bot(&self) -> Bot {
+        self.wrap(self.build())
+    }
+
+    fn wrap(&self, mut trader: SimpleTrading) -> Bot {
+        match &self.signal {
+            Some(signal) => Bot::Signal(SignalTrading::new(trader, &signal.interval, signal.source())),
+            None => {
+                trader.movement_entries = true;
+                Bot::Movement(trader)
+            }
+        }
+    }
+
+    // Retune a running bot and switch it between movement and signal entries if needed.
+    fn rebuild(&self, bot: Bot) -> Bot {
+        match (bot, &self.signal) {
+            (Bot::Signal(mut bot), Some(signal)) => {
+                self.retune(&mut bot.trader);
+                bot.set_source(&signal.interval, signal.source());
+                Bot::Signal(bot)
+            }
+            (bot, _) => {
+                let mut trader = bot.into_trader();
+                self.retune(&mut trader);
+                self.wrap(trader)
+            }
+        }
+    }
+
+    // Update the parameters of a running bot, its totals and open position are kept.
+    fn retune(&self, bot: &mut SimpleTrading) {
+        bot.family = self.family.to_string();
+        bot.symbol = self.symbol.to_string();
+        bot.take_profit_percentage = self.take_profit_percentage / 100.0;
+        bot.stop_loss_value_usd = self.stop_loss_usd;
+        bot.watch_movement_percentage = self.watch_movement_percentage;
+        bot.costs = self.costs.unwrap_or_default();
+        bot.rules = self.rules.unwrap_or_default();
+        bot.sizing = self.sizing;
+        bot.leverage = self.leverage;
+
+        // a new starting balance is a deposit or a withdrawal, the realized P&L is kept.
+        bot.account.balance += self.balance - bot.account.starting_balance;
+        bot.account.starting_balance = self.balance;
+        bot.account.reinvest = self.reinvest;
+
+        if bot.atr.period != self.exits.atr_period() {
+            bot.atr = AverageTrueRange::new(self.exits.atr_period());
+        }
+
+        bot.exits = self.exits.clone();
+        bot.limits = self.limits;
+    }
+}
+
+impl FleetConfig {
+    pub fn validate(&self) -> Result<(), FleetError> {
+        let mut names = HashSet::new();
+
+        for (index, bot) in self.bots.iter().enumerate() {
+            let error = |message: String| FleetError {
+                index,
+                name: bot.name.to_string(),
+                message,
+            };
+
+            bot.validate().map_err(error)?;
+
+            if !names.insert(bot.name.as_str()) {
+                return Err(error("name is already used by another bot".to_string()));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn build(&self) -> Vec<Bot> {
+        self.bots.iter().map(|bot| bot.bot()).collect()
+    }
+
+    // One bot per symbol for the entries listing several symbols.
+    pub fn expand_symbols(&mut self) {
+        let bots = std::mem::take(&mut self.bots);
+
+        for bot in bots {
+            if bot.symbols.is_empty() {
+                self.bots.push(bot);
+                continue;
+            }
+
+            for symbol in &bot.symbols {
+                let symbol = symbol.to_uppercase();
+
+                self.bots.push(BotConfig {
+                    name: format!("{}:{}", symbol, bot.name),
+                    symbol,
+                    symbols: Vec::new(),
+                    ..bot.clone()
+                });
+            }
+        }
+    }
+
+    pub fn validate_intervals(&self) -> Result<(), String> {
+        for interval in &self.intervals {
+            if interval_ms(interval).is_none_or(|length| length <= 0) {
+                return Err(format!("intervals: {:?} is not a kline interval", interval));
+            }
+        }
+
+        for interval in &self.aggregated_intervals {
+            if interval_ms(interval).is_none_or(|length| length <= TICK_INTERVAL_MS) {
+                return Err(format!(
+                    "aggregated_intervals: {:?} must be an interval longer than {}",
+                    interval, TICK_INTERVAL
+                ));
+            }
+
+            if self.intervals.contains(interval) {
+                return Err(format!("{} is both subscribed and aggregated", interval));
+            }
+        }
+
+        for bot in &self.bots {
+            if let Some(signal) = &bot.signal {
+                if !self.subscribed_intervals().contains(&signal.interval)
+                    && !self.aggregated_intervals.contains(&signal.interval)
+                {
+                    return Err(format!(
+                        "{} reads {} klines, add the interval to intervals or aggregated_intervals",
+                        bot.name, signal.interval
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Kline streams of each symbol, the 1s ticks first.
+    pub fn subscribed_intervals(&self) -> Vec<String> {
+        let mut intervals = vec![TICK_INTERVAL.to_string()];
+
+        for interval in &self.intervals {
+            if !intervals.contains(interval) {
+                intervals.push(interval.to_string());
+            }
+        }
+
+        intervals
+    }
+
+    // Symbols the bots trade, the websocket subscribes to their streams.
+    pub fn traded_symbols(&self) -> BTreeSet<String> {
+        self.bots.iter().map(|bot| bot.symbol.to_string()).collect()
+    }
+
+    // Bot names grouped by family, used for reporting.
+    pub fn families(&self) -> BTreeMap<String, Vec<String>> {
+        let mut families: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+        for bot in &self.bots {
+            families
+                .entry(bot.family.to_string())
+                .or_default()
+                .push(bot.name.to_string());
+        }
+
+        families
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct FleetChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub retuned: Vec<String>,
+    // Positions closed by the `close` policy, at the last event time of their bot.
+    pub closed: Vec<(String, TradeRecord)>,
+}
+
+impl FleetChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.retuned.is_empty()
+    }
+}
+
+// A running bot, the SimpleTrading managing the position of either kind is reached by Deref.
+pub enum Bot {
+    Movement(SimpleTrading),
+    Signal(SignalTrading),
+}
+
+impl Bot {
+    pub fn strategy_mut(&mut self) -> &mut dyn Strategy {
+        match self {
+            Bot::Movement(bot) => bot,
+            Bot::Signal(bot) => bot,
+        }
+    }
+
+    fn into_trader(self) -> SimpleTrading {
+        match self {
+            Bot::Movement(bot) => bot,
+            Bot::Signal(bot) => bot.trader,
+        }
+    }
+}
+
+impl Deref for Bot {
+    type Target = SimpleTrading;
+
+    fn deref(&self) -> &SimpleTrading {
+        match self {
+            Bot::Movement(bot) => bot,
+            Bot::Signal(bot) => &bot.trader,
+        }
+    }
+}
+
+impl DerefMut for Bot {
+    fn deref_mut(&mut self) -> &mut SimpleTrading {
+        match self {
+            Bot::Movement(bot) => bot,
+            Bot::Signal(bot) => &mut bot.trader,
+        }
+    }
+}
+
+pub struct Fleet {
+    pub config: FleetConfig,
+    pub bots: Vec<Bot>,
+}
+
+impl Fleet {
+    pub fn new(config: FleetConfig) -> Self {
+        let bots = config.build();
+
+        Self { config, bots }
+    }
+
+    // Resume every bot from the snapshot saved by the previous process.
+    pub fn load_state(&mut self) -> usize {
+        self.bots.iter_mut().map(|bot| bot.load_state()).filter(|restored| *restored).count()
+    }
+
+    // Every running bot, whatever its strategy.
+    pub fn strategies_mut(&mut self) -> impl Iterator<Item = &mut dyn Strategy> {
+        self.bots.iter_mut().map(|bot| bot.strategy_mut())
+    }
+
+    // Apply a new fleet definition to the running bots: new bots start fresh, removed bots are
+    // retired according to `on_remove` and changed bots keep their profits and losses.
+    pub fn apply(&mut self, config: FleetConfig) -> FleetChanges {
+        let mut changes = FleetChanges::default();
+
+        let previous: HashMap<String, BotConfig> = self
+            .config
+            .bots
+            .drain(..)
+            .map(|bot| (bot.name.to_string(), bot))
+            .collect();
+
+        let mut running: HashMap<String, Bot> = self
+            .bots
+            .drain(..)
+            .map(|bot| (bot.name.to_string(), bot))
+            .collect();
+
+        for bot_config in &config.bots {
+            let bot = match running.remove(&bot_config.name) {
+                Some(mut bot) => {
+                    if previous.get(&bot_config.name) != Some(bot_config) {
+                        if bot.symbol != bot_config.symbol {
+                            changes.closed.extend(close_position(&mut bot, config.on_remove));
+                        }
+
+                        changes.retuned.push(bot_config.name.to_string());
+                        bot_config.rebuild(bot)
+                    } else {
+                        bot
+                    }
+                }
+                None => {
+                    changes.added.push(bot_config.name.to_string());
+                    bot_config.bot()
+                }
+            };
+
+            self.bots.push(bot);
+        }
+
+        for (name, mut bot) in running {
+            changes.closed.extend(retire(&mut bot, config.on_remove));
+            changes.removed.push(name);
+        }
+
+        changes.removed.sort();
+        changes.closed.sort_by(|a, b| a.0.cmp(&b.0));
+        self.config = config;
+
+        changes
+    }
+}
+
+// Event time like the ticks, so replays close the position where the live run did.
+fn close_position(bot: &mut SimpleTrading, policy: RemovePolicy) -> Option<(String, TradeRecord)> {
+    match policy {
+        RemovePolicy::Close => bot
+            .close_at(bot.last_price, bot.last_time, ExitReason::Manual)
+            .map(|trade| (bot.name.to_string(), trade)),
+        RemovePolicy::Abandon => {
+            bot.abandon();
+            None
+        }
+    }
+}
+
+fn retire(bot: &mut SimpleTrading, policy: RemovePolicy) -> Option<(String, TradeRecord)> {
+    let closed = close_position(bot, policy);
+    bot.forget_state();
+    closed
+}
+
+// Polls the fleet file modification time, at most every WATCH_INTERVAL, from the websocket loop.
+pub struct FleetWatcher {
+    path: String,
+    modified: Option<SystemTime>,
+    checked_at: Instant,
+}
+
+impl FleetWatcher {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            modified: modified_at(path),
+            checked_at: Instant::now(),
+        }
+    }
+
+    // Returns the new fleet definition when the file changed and is valid.
+    pub fn poll(&mut self) -> Option<FleetConfig> {
+        if self.checked_at.elapsed() < WATCH_INTERVAL {
+            return None;
+        }
+
+        self.checked_at = Instant::now();
+
+        let modified = modified_at(&self.path);
+
+        if modified == self.modified {
+            return None;
+        }
+
+        self.modified = modified;
+
+        match load_fleet_config(&self.path) {
+            Ok(config) => Some(config),
+            Err(e) => {
+                println!("VHAKM: Ignoring fleet update {}: {}", self.path, e);
+                None
+            }
+        }
+    }
+}
+
+fn modified_at(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+pub fn load_fleet_config(path: &str) -> Result<FleetConfig, Box<dyn std::error::Error>> {
+    let mut fleet: FleetConfig = config::Config::builder()
+        .add_source(config::File::with_name(path))
+        .build()?
+        .try_deserialize()?;
+
+    fleet.costs.validate().map_err(|e| format!("costs: {}", e))?;
+
+    for (symbol, rules) in &fleet.symbols {
+        rules.validate().map_err(|e| format!("symbols.{}: {}", symbol, e))?;
+    }
+
+    if let Some(depth) = &fleet.depth {
+        depth.validate().map_err(|e| format!("depth: {}", e))?;
+    }
+
+    if let Some(order_flow) = &fleet.order_flow {
+        order_flow.validate().map_err(|e| format!("order_flow: {}", e))?;
+    }
+
+    fleet.variations.validate().map_err(|e| format!("variations: {}", e))?;
+
+    fleet.expand_symbols();
+    fleet.validate_intervals()?;
+
+    // bots without their own costs or rules use the fleet wide ones.
+    for bot in &mut fleet.bots {
+        bot.costs.get_or_insert(fleet.costs);
+
+        if let Some(rules) = fleet.symbols.get(&bot.symbol) {
+            bot.rules.get_or_insert(*rules);
+        }
+    }
+
+    fleet.validate()?;
+
+    Ok(fleet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(toml: &str) -> FleetConfig {
+        config::Config::builder()
+            .add_source(config::File::from_str(toml, config::FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap()
+    }
+
+    const FLEET: &str = r#"
+        [[bots]]
+        name = "test:BTCUSDT:A"
+        family = "vhakm"
+        symbol = "BTCUSDT"
+        take_profit_percentage = 0.03
+        stop_loss_usd = 5.0
+        watch_movement_percentage = 0.09
+
+        [[bots]]
+        name = "test:BTCUSDT:Scalper"
+        family = "gemini"
+        symbol = "BTCUSDT"
+        take_profit_percentage = 0.015
+        stop_loss_usd = 5.0
+        watch_movement_percentage = 0.02
+    "#;
+
+    #[test]
+    fn test_build_fleet() {
+        let fleet = parse(FLEET);
+        assert!(fleet.validate().is_ok());
+
+        let bots = fleet.build();
+        assert_eq!(bots.len(), 2);
+        assert_eq!(bots[1].name, "test:BTCUSDT:Scalper");
+        assert_eq!(bots[1].family, "gemini");
+        assert_eq!(bots[1].symbol, "BTCUSDT");
+        assert_eq!(bots[1].take_profit_percentage, 0.015 / 100.0);
+        assert_eq!(bots[1].watch_movement_percentage, 0.02);
+
+        let families = fleet.families();
+        assert_eq!(families["vhakm"], vec!["test:BTCUSDT:A".to_string()]);
+    }
+
+    #[test]
+    fn test_validation_points_at_entry() {
+        let mut fleet = parse(FLEET);
+        fleet.bots[1].take_profit_percentage = -1.0;
+
+        let error = fleet.validate().unwrap_err();
+        assert_eq!(error.index, 1);
+        assert_eq!(error.name, "test:BTCUSDT:Scalper");
+        assert!(error.to_string().starts_with("bots[1] (\"test:BTCUSDT:Scalper\")"));
+    }
+
+    #[test]
+    fn test_shipped_fleet() {
+        let fleet = load_fleet_config(DEFAULT_FLEET_PATH).unwrap();
+        assert_eq!(fleet.families().len(), 3);
+        assert!(fleet.bots.iter().all(|bot| bot.costs == Some(fleet.costs)));
+    }
+
+    #[test]
+    fn test_bot_costs() {
+        let mut fleet = parse(FLEET);
+        fleet.bots[0].costs = Some(TradingCosts {
+            funding_rate_percentage: 0.01,
+            ..TradingCosts::default()
+        });
+
+        assert_eq!(fleet.build()[0].costs.funding_rate_percentage, 0.01);
+
+        fleet.bots[0].costs.as_mut().unwrap().fees.taker_percentage = -0.05;
+
+        let error = fleet.validate().unwrap_err();
+        assert_eq!(error.index, 0);
+        assert!(error.message.starts_with("costs: fees.taker_percentage"));
+    }
+
+    #[test]
+    fn test_apply_changes() {
+        let mut fleet = Fleet::new(parse(FLEET));
+
+        fleet.bots[0].profits = 12.0;
+        fleet.bots[0].losses = 3.0;
+
+        let mut config = parse(FLEET);
+        config.bots[0].take_profit_percentage = 0.05;
+        config.bots.remove(1);
+        config.bots.push(BotConfig {
+            name: "test:BTCUSDT:Added".to_string(),
+            family: "chatgpt".to_string(),
+            ..config.bots[0].clone()
+        });
+
+        let changes = fleet.apply(config);
+
+        assert_eq!(changes.added, vec!["test:BTCUSDT:Added".to_string()]);
+        assert_eq!(changes.removed, vec!["test:BTCUSDT:Scalper".to_string()]);
+        assert_eq!(changes.retuned, vec!["test:BTCUSDT:A".to_string()]);
+
+        assert_eq!(fleet.bots.len(), 2);
+        assert_eq!(fleet.bots[0].profits, 12.0);
+        assert_eq!(fleet.bots[0].losses, 3.0);
+        assert_eq!(fleet.bots[0].take_profit_percentage, 0.05 / 100.0);
+        assert_eq!(fleet.bots[1].profits, 0.0);
+
+        let changes = fleet.apply(parse(FLEET));
+        assert_eq!(changes.removed, vec!["test:BTCUSDT:Added".to_string()]);
+        assert!(changes.retuned.contains(&"test:BTCUSDT:A".to_string()));
+    }
+
+    fn open_long() -> SimpleTrading {
+        let mut bot = parse(FLEET).bots[0].build();
+
+        for price in [100_000.0, 100_100.0, 100_120.0, 100_140.0] {
+            bot.run(price);
+        }
+
+        assert!(bot.trade_active);
+
+        bot
+    }
+
+    #[test]
+    fn test_remove_policy() {
+        let mut bot = open_long();
+
+        // retiring drops the saved state, which the test bots never wrote.
+        assert!(!bot.persist);
+        retire(&mut bot, RemovePolicy::Abandon);
+        assert!(!bot.trade_active);
+        assert_eq!(bot.profits, 0.0);
+
+        let mut bot = open_long();
+        retire(&mut bot, RemovePolicy::Close);
+        assert!(!bot.trade_active);
+        assert_eq!(bot.profits, 20.0);
+    }
+
+    #[test]
+    fn test_sizing() {
+        let toml = FLEET.replace(
+            "watch_movement_percentage = 0.02",
+            "watch_movement_percentage = 0.02\n        leverage = 10.0\n        balance = 1000.0\n        sizing = { mode = \"balance_percentage\", value = 20.0 }",
+        );
+
+        let fleet = parse(&toml);
+        assert!(fleet.validate().is_ok());
+        assert_eq!(fleet.bots[0].sizing, PositionSizing::Quantity(1.0));
+        assert_eq!(fleet.bots[0].leverage, 1.0);
+
+        let bot = fleet.bots[1].build();
+        assert_eq!(bot.sizing, PositionSizing::BalancePercentage(20.0));
+        assert_eq!(bot.leverage, 10.0);
+        assert_eq!(bot.account.balance, 1000.0);
+
+        let mut fleet = parse(&toml);
+        fleet.bots[1].balance = 0.0;
+        assert_eq!(fleet.validate().unwrap_err().index, 1);
+    }
+
+    #[test]
+    fn test_exits() {
+        let toml = FLEET.replace(
+            "watch_movement_percentage = 0.02",
+            "watch_movement_percentage = 0.02\n        exits.trailing_stop = { mode = \"atr\", multiple = 2.0, period = 20 }\n        exits.break_even = { trigger_percentage = 0.01 }\n        exits.partial_take_profits = [{ percentage = 0.01, fraction = 0.5 }]",
+        );
+
+        let fleet = parse(&toml);
+        assert!(fleet.validate().is_ok());
+        assert_eq!(fleet.bots[0].exits, ExitRules::default());
+
+        let bot = fleet.bots[1].build();
+        assert_eq!(
+            bot.exits.trailing_stop,
+            Some(crate::exits::TrailingStop::Atr {
+                multiple: 2.0,
+                period: 20
+            })
+        );
+        assert_eq!(bot.exits.break_even.unwrap().offset_percentage, 0.0);
+        assert_eq!(bot.exits.partial_take_profits.len(), 1);
+        assert_eq!(bot.atr.period, 20);
+
+        let mut fleet = parse(&toml);
+        fleet.bots[1].exits.partial_take_profits[0].fraction = 1.0;
+        assert_eq!(fleet.validate().unwrap_err().index, 1);
+    }
+
+    #[test]
+    fn test_limits() {
+        let toml = FLEET.replace(
+            "watch_movement_percentage = 0.02",
+            "watch_movement_percentage = 0.02\n        limits = { max_holding_seconds = 3600, stop_loss_cooldown_seconds = 300, max_trades_per_day = 20 }",
+        );
+
+        let fleet = parse(&toml);
+        assert!(fleet.validate().is_ok());
+        assert_eq!(fleet.bots[0].limits, TradeLimits::default());
+
+        let bot = fleet.bots[1].build();
+        assert_eq!(bot.limits.max_holding_seconds, Some(3600));
+        assert_eq!(bot.limits.cooldown_ms(), 300_000);
+        assert_eq!(bot.limits.max_trades_per_day, Some(20));
+
+        let mut fleet = parse(&toml);
+        fleet.bots[1].limits.max_trades_per_day = Some(0);
+        assert_eq!(fleet.validate().unwrap_err().index, 1);
+    }
+
+    #[test]
+    fn test_signal_bot() {
+        let toml = FLEET.replace(
+            "watch_movement_percentage = 0.02",
+            "watch_movement_percentage = 0.02\n        signal = { strategy = \"src/bots/weighted.json\", key = \"indicators:BTCUSDT:1m\", interval = \"1m\" }",
+        );
+
+        let config = parse(&toml);
+        assert!(config.validate().is_ok());
+
+        let mut fleet = Fleet::new(config);
+        assert!(matches!(fleet.bots[0], Bot::Movement(_)));
+        assert!(matches!(&fleet.bots[1], Bot::Signal(bot) if bot.interval == "1m"));
+        assert!(!fleet.bots[1].movement_entries);
+
+        fleet.bots[1].profits = 4.0;
+        let changes = fleet.apply(parse(FLEET));
+
+        assert_eq!(changes.retuned, vec!["test:BTCUSDT:Scalper".to_string()]);
+        assert!(matches!(fleet.bots[1], Bot::Movement(_)));
+        assert!(fleet.bots[1].movement_entries);
+        assert_eq!(fleet.bots[1].profits, 4.0);
+
+        let mut config = parse(&toml);
+        config.bots[1].signal.as_mut().unwrap().strategy = "missing.json".to_string();
+        assert_eq!(config.validate().unwrap_err().index, 1);
+    }
+
+    #[test]
+    fn test_intervals() {
+        let toml = FLEET.replace(
+            "watch_movement_percentage = 0.02",
+            "watch_movement_percentage = 0.02\n        signal = { strategy = \"src/bots/weighted.json\", key = \"indicators:BTCUSDT:5m\", interval = \"5m\" }",
+        );
+
+        let mut config = parse(&toml);
+        assert!(config.validate_intervals().is_err());
+
+        config.intervals = vec!["1m".to_string(), "1s".to_string()];
+        config.aggregated_intervals = vec!["5m".to_string(), "1h".to_string()];
+        assert!(config.validate_intervals().is_ok());
+        assert_eq!(config.subscribed_intervals(), vec!["1s", "1m"]);
+
+        config.aggregated_intervals.push("1m".to_string());
+        assert!(config.validate_intervals().is_err());
+
+        config.aggregated_intervals = vec!["5m".to_string(), "1s".to_string()];
+        assert!(config.validate_intervals().is_err());
+    }
+
+    #[test]
+    fn test_duplicate_names() {
+        let mut fleet = parse(FLEET);
+        fleet.bots[1].name = "test:BTCUSDT:A".to_string();
+
+        let error = fleet.validate().unwrap_err();
+        assert_eq!(error.index, 1);
+    }
+
+    #[test]
+    fn test_symbols() {
+        let path = std::env::temp_dir().join("eddie_fleet_symbols.toml");
+        std::fs::write(
+            &path,
+            r#"
+            [symbols.ETHUSDT]
+            tick_size = 0.01
+            lot_size = 0.001
+
+            [[bots]]
+            name = "TEST"
+            family = "vhakm"
+            symbols = ["BTCUSDT", "ethusdt"]
+            take_profit_percentage = 0.03
+            stop_loss_usd = 5.0
+            watch_movement_percentage = 0.04
+
+            [[bots]]
+            name = "SOLUSDT:TEST"
+            family = "vhakm"
+            symbol = "SOLUSDT"
+            rules = { tick_size = 0.001 }
+            take_profit_percentage = 0.03
+            stop_loss_usd = 5.0
+            watch_movement_percentage = 0.04
+            "#,
+        )
+        .unwrap();
+
+        let config = load_fleet_config(path.to_str().unwrap()).unwrap();
+        let names: Vec<&str> = config.bots.iter().map(|bot| bot.name.as_str()).collect();
+
+        assert_eq!(names, vec!["BTCUSDT:TEST", "ETHUSDT:TEST", "SOLUSDT:TEST"]);
+        assert_eq!(config.bots[1].symbol, "ETHUSDT");
+        assert_eq!(config.bots[0].rules, None);
+        assert_eq!(config.bots[1].rules.unwrap().lot_size, 0.001);
+        assert_eq!(config.bots[2].rules.unwrap().tick_size, 0.001);
+        assert_eq!(
+            config.traded_symbols().into_iter().collect::<Vec<_>>(),
+            vec!["BTCUSDT", "ETHUSDT", "SOLUSDT"]
+        );
+
+        let bots = config.build();
+        assert_eq!(bots[1].rules.tick_size, 0.01);
+    }
+}
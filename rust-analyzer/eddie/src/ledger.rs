@@ -0,0 +1,154 @@
+// One record per closed trade, stored in redis sorted sets scored by the exit time (ms):
+// `trades:ledger` for the whole fleet, `trades:ledger:{bot}` for each bot and
+// `trades:ledger:symbol:{SYMBOL}` for each traded symbol.
+
+use redis::Commands;
+use serde::{Deserialize, Serialize};
+
+pub const LEDGER_KEY: &str = "trades:ledger";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Long,
+    Short,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ExitReason {
+    #[serde(rename = "TP")]
+    TakeProfit,
+    #[serde(rename = "SL")]
+    StopLoss,
+    // Stop moved to the entry (plus offset) after a favourable move.
+    #[serde(rename = "BE")]
+    BreakEven,
+    #[serde(rename = "TS")]
+    TrailingStop,
+    // Part of the position closed at one of the partial take profit levels.
+    #[serde(rename = "PTP")]
+    PartialTakeProfit,
+    // Closed at the market after the maximum holding time.
+    #[serde(rename = "time")]
+    MaxHoldingTime,
+    // Closed by an opposite signal of a signal-driven bot.
+    #[serde(rename = "signal")]
+    Signal,
+    #[serde(rename = "manual")]
+    Manual,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TradeRecord {
+    pub bot: String,
+    pub family: String,
+    pub symbol: String,
+    pub side: Side,
+    // Base asset quantity, P&L and costs below are for the whole quantity.
+    pub quantity: f64,
+    pub leverage: f64,
+    pub entry_price: f64,
+    pub entry_time: i64,
+    pub exit_price: f64,
+    pub exit_time: i64,
+    pub exit_reason: ExitReason,
+    // Price difference between entry and exit, before any trading cost.
+    pub gross_pnl: f64,
+    pub fees: f64,
+    pub slippage: f64,
+    pub funding: f64,
+    pub net_pnl: f64,
+    // Worst and best unrealized result seen while the trade was open, both positive values.
+    pub max_adverse_excursion: f64,
+    pub max_favourable_excursion: f64,
+}
+
+impl TradeRecord {
+    pub fn is_win(&self) -> bool {
+        self.net_pnl > 0.0
+    }
+}
+
+pub fn ledger_key(bot: &str) -> String {
+    format!("{}:{}", LEDGER_KEY, bot)
+}
+
+pub fn symbol_key(symbol: &str) -> String {
+    format!("{}:symbol:{}", LEDGER_KEY, symbol)
+}
+
+pub fn record(con: &mut redis::Connection, trade: &TradeRecord) -> redis::RedisResult<()> {
+    let value = serde_json::to_string(trade).unwrap();
+
+    con.zadd::<_, _, _, ()>(LEDGER_KEY, &value, trade.exit_time)?;
+    con.zadd::<_, _, _, ()>(ledger_key(&trade.bot), &value, trade.exit_time)?;
+    con.zadd::<_, _, _, ()>(symbol_key(&trade.symbol), &value, trade.exit_time)?;
+
+    Ok(())
+}
+
+// Trades of one bot (or the whole fleet when `bot` is None) closed between `from` and `to` (ms).
+pub fn load(
+    con: &mut redis::Connection,
+    bot: Option<&str>,
+    from: i64,
+    to: i64,
+) -> Result<Vec<TradeRecord>, Box<dyn std::error::Error>> {
+    load_key(con, &bot.map(ledger_key).unwrap_or(LEDGER_KEY.to_string()), from, to)
+}
+
+// Trades of a ledger key closed between `from` and `to` (ms).
+pub fn load_key(
+    con: &mut redis::Connection,
+    key: &str,
+    from: i64,
+    to: i64,
+) -> Result<Vec<TradeRecord>, Box<dyn std::error::Error>> {
+    let values: Vec<String> = con.zrangebyscore(key, from, to)?;
+
+    let mut trades = Vec::with_capacity(values.len());
+
+    for value in values {
+        trades.push(serde_json::from_str(&value)?);
+    }
+
+    Ok(trades)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_format() {
+        let trade = TradeRecord {
+            bot: "test:BTCUSDT:C".to_string(),
+            family: "vhakm".to_string(),
+            symbol: "BTCUSDT".to_string(),
+            side: Side::Short,
+            quantity: 1.0,
+            leverage: 1.0,
+            entry_price: 100_000.0,
+            entry_time: 1_000,
+            exit_price: 99_930.0,
+            exit_time: 61_000,
+            exit_reason: ExitReason::TakeProfit,
+            gross_pnl: 70.0,
+            fees: 70.0,
+            slippage: 1.0,
+            funding: 0.0,
+            net_pnl: -1.0,
+            max_adverse_excursion: 4.0,
+            max_favourable_excursion: 70.0,
+        };
+
+        let json = serde_json::to_string(&trade).unwrap();
+
+        assert!(json.contains(r#""side":"short""#));
+        assert!(json.contains(r#""exit_reason":"TP""#));
+        assert_eq!(serde_json::from_str::<TradeRecord>(&json).unwrap(), trade);
+        assert!(!trade.is_win());
+        assert_eq!(ledger_key(&trade.bot), "trades:ledger:test:BTCUSDT:C");
+        assert_eq!(symbol_key(&trade.symbol), "trades:ledger:symbol:BTCUSDT");
+    }
+}
@@ -0,0 +1,84 @@
+// Time based limits of a bot: how long a position may stay open, how long the bot waits after a
+// stop loss and how many trades it may open per UTC day. Everything is measured on event time
+// so live trading and replays behave the same.
+
+use serde::{Deserialize, Serialize};
+
+pub const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TradeLimits {
+    // Positions still open after this many seconds are closed at the market.
+    #[serde(default)]
+    pub max_holding_seconds: Option<u64>,
+    // No new watch triggers for this many seconds after a stop loss.
+    #[serde(default)]
+    pub stop_loss_cooldown_seconds: u64,
+    // Trades opened per UTC day, counted on entry.
+    #[serde(default)]
+    pub max_trades_per_day: Option<u32>,
+}
+
+impl TradeLimits {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_holding_seconds == Some(0) {
+            return Err("max_holding_seconds must be positive".to_string());
+        }
+
+        if self.max_trades_per_day == Some(0) {
+            return Err("max_trades_per_day must be positive".to_string());
+        }
+
+        Ok(())
+    }
+
+    pub fn holding_expired(&self, entry_time: i64, time: i64) -> bool {
+        match self.max_holding_seconds {
+            Some(seconds) => time - entry_time >= seconds as i64 * 1000,
+            None => false,
+        }
+    }
+
+    pub fn cooldown_ms(&self) -> i64 {
+        self.stop_loss_cooldown_seconds as i64 * 1000
+    }
+
+    pub fn daily_cap_reached(&self, trades_today: u32) -> bool {
+        match self.max_trades_per_day {
+            Some(cap) => trades_today >= cap,
+            None => false,
+        }
+    }
+}
+
+// UTC day number of an event time in milliseconds.
+pub fn trading_day(time: i64) -> i64 {
+    time.div_euclid(DAY_MS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limits() {
+        let limits = TradeLimits {
+            max_holding_seconds: Some(60),
+            stop_loss_cooldown_seconds: 30,
+            max_trades_per_day: Some(2),
+        };
+
+        assert!(limits.validate().is_ok());
+        assert!(!limits.holding_expired(1_000, 60_999));
+        assert!(limits.holding_expired(1_000, 61_000));
+        assert_eq!(limits.cooldown_ms(), 30_000);
+        assert!(!limits.daily_cap_reached(1));
+        assert!(limits.daily_cap_reached(2));
+
+        assert!(!TradeLimits::default().holding_expired(0, i64::MAX));
+        assert!(!TradeLimits::default().daily_cap_reached(u32::MAX));
+
+        assert_eq!(trading_day(DAY_MS - 1), 0);
+        assert_eq!(trading_day(DAY_MS), 1);
+    }
+}
@@ -1,31 +1,91 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
+#![allow(clippy::module_inception)]
 
 use binance::api::Binance;
-use binance::websockets::*;
-use redis::{self, Commands, PubSubCommands};
-use std::collections::HashMap;
+use binance::market::Market;
+use sources::MarketSource;
+use std::env;
 use std::sync::atomic::AtomicBool;
-use tokio;
-use tokio::time::{self, Duration};
+use tokio::time::Duration;
 
+mod account;
+mod backtest;
+mod candles;
+mod costs;
 mod definitions;
+mod dumps;
+mod exits;
+mod fetcher;
+mod fleet;
+mod ledger;
+mod limits;
+mod metrics;
+mod order_book;
+mod order_flow;
 mod processors;
+mod reconnect;
+mod replay;
+mod runner;
+mod signal_trading;
 mod simple_trading;
+mod sizing;
+mod sources;
+mod strategy;
+mod sweep;
+mod symbols;
+mod synthetic;
 mod utils;
+mod walk_forward;
 
-const SYMBOLS: [&str; 1] = ["BTCUSDT"];
+// Kline streams of every traded symbol, its diff depth stream when the fleet keeps books and its
+// aggregated trade stream when it follows the order flow.
+fn endpoints(config: &fleet::FleetConfig) -> Vec<String> {
+    let intervals = config.subscribed_intervals();
 
-fn endpoints() -> Vec<String> {
-    SYMBOLS
+    config
+        .traded_symbols()
         .iter()
-        .map(|symbol| vec![format!("{}@kline_1s", symbol.to_lowercase())])
-        .flatten()
+        .flat_map(|symbol| {
+            let symbol = symbol.to_lowercase();
+
+            let klines = intervals
+                .iter()
+                .map(|interval| format!("{}@kline_{}", symbol, interval))
+                .collect::<Vec<String>>();
+
+            let depth = config.depth.as_ref().map(|_| format!("{}@depth@100ms", symbol));
+            let trades = config.order_flow.as_ref().map(|_| format!("{}@aggTrade", symbol));
+
+            klines.into_iter().chain(depth).chain(trades)
+        })
         .collect::<Vec<String>>()
 }
 
 #[tokio::main]
 async fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    // offline commands, the live bots run without arguments.
+    let result = match args.get(1).map(String::as_str) {
+        Some("backtest") => Some(backtest::run_cli(&args[2..])),
+        Some("import") => Some(dumps::run_cli(&args[2..])),
+        Some("metrics") => Some(metrics::run_cli(&args[2..])),
+        Some("replay") => Some(replay::run_cli(&args[2..])),
+        Some("sweep") => Some(sweep::run_cli(&args[2..])),
+        Some("walk-forward") => Some(walk_forward::run_cli(&args[2..])),
+        _ => None,
+    };
+
+    if let Some(result) = result {
+        if let Err(e) = result {
+            println!("VHAKM: {} failed: {}", args[1], e);
+            std::process::exit(1);
+        }
+
+        return;
+    }
+
     setup_binance();
 
     loop {
@@ -38,213 +98,67 @@ fn setup_binance() {
 
     println!("VHAKM: Starting the trading bot...");
 
-    let mut vhakms = vec![
-        simple_trading::SimpleTrading::new(
-            "BTCUSDT:BIRTH".to_string(),
-            0.0,
-            0.03 / 100.0,
-            5.0,
-            0.04,
-        ),
-        simple_trading::SimpleTrading::new("BTCUSDT:V0".to_string(), 0.0, 0.03 / 100.0, 5.0, 0.06),
-        simple_trading::SimpleTrading::new("BTCUSDT:V1".to_string(), 0.0, 0.03 / 100.0, 5.0, 0.09),
-        simple_trading::SimpleTrading::new("BTCUSDT:V2".to_string(), 0.0, 0.03 / 100.0, 5.0, 0.1),
-        simple_trading::SimpleTrading::new("BTCUSDT:V3".to_string(), 0.0, 0.07 / 100.0, 15.0, 0.12),
-        simple_trading::SimpleTrading::new("BTCUSDT:V4".to_string(), 0.0, 0.1 / 100.0, 25.0, 0.15),
-        simple_trading::SimpleTrading::new("BTCUSDT:V5".to_string(), 0.0, 0.07 / 100.0, 25.0, 0.07),
-        simple_trading::SimpleTrading::new("BTCUSDT:V6".to_string(), 0.0, 0.02 / 100.0, 0.0, 0.04),
-        simple_trading::SimpleTrading::new("BTCUSDT:V7".to_string(), 0.0, 0.02 / 100.0, 0.0, 0.08),
-        simple_trading::SimpleTrading::new(
-            "BTCUSDT:V8".to_string(),
-            0.0,
-            0.035 / 100.0,
-            10.0,
-            0.08,
-        ),
-    ];
-
-    let mut chatgpts = vec![
-        simple_trading::SimpleTrading::new("BTCUSDT:V1".to_string(), 0.0, 0.025 / 100.0, 5.0, 0.05),
-        simple_trading::SimpleTrading::new(
-            "BTCUSDT:V2".to_string(),
-            0.0,
-            0.035 / 100.0,
-            10.0,
-            0.08,
-        ),
-        simple_trading::SimpleTrading::new(
-            "CHATGPT:BTCUSDT:V3".to_string(),
-            0.0,
-            0.05 / 100.0,
-            15.0,
-            0.10,
-        ),
-        simple_trading::SimpleTrading::new(
-            "CHATGPT:BTCUSDT:V4".to_string(),
-            0.0,
-            0.02 / 100.0,
-            8.0,
-            0.06,
-        ),
-        simple_trading::SimpleTrading::new(
-            "CHATGPT:BTCUSDT:V5".to_string(),
-            0.0,
-            0.06 / 100.0,
-            20.0,
-            0.12,
-        ),
-        simple_trading::SimpleTrading::new(
-            "CHATGPT:BTCUSDT:V6".to_string(),
-            0.0,
-            0.04 / 100.0,
-            12.0,
-            0.09,
-        ),
-        simple_trading::SimpleTrading::new(
-            "CHATGPT:BTCUSDT:V7".to_string(),
-            0.0,
-            0.03 / 100.0,
-            10.0,
-            0.07,
-        ),
-        simple_trading::SimpleTrading::new(
-            "CHATGPT:BTCUSDT:V8".to_string(),
-            0.0,
-            0.07 / 100.0,
-            25.0,
-            0.15,
-        ),
-        simple_trading::SimpleTrading::new(
-            "CHATGPT:BTCUSDT:V9".to_string(),
-            0.0,
-            0.045 / 100.0,
-            18.0,
-            0.11,
-        ),
-        simple_trading::SimpleTrading::new(
-            "CHATGPT:BTCUSDT:V10".to_string(),
-            0.0,
-            0.015 / 100.0,
-            7.0,
-            0.04,
-        ),
-    ];
-
-    let mut gemini_bots = gemini_bots();
-
-    let mut web_socket = WebSockets::new(|event: WebsocketEvent| {
-        match event {
-            WebsocketEvent::Kline(event) => {
-                let current_price = event.kline.close.parse::<f64>().unwrap();
-
-                for bot in &mut vhakms {
-                    bot.run(current_price);
-                }
-
-                for bot in &mut chatgpts {
-                    bot.run(current_price);
-                }
-
-                for bot in &mut gemini_bots {
-                    bot.run(current_price);
-                }
+    let fleet_path = env::var("EDDIE_FLEET").unwrap_or(fleet::DEFAULT_FLEET_PATH.to_string());
 
-            }
-            _ => {
-                println!("Received event: {:?}", event);
-            }
-        };
-        Ok(())
-    });
+    let config = match fleet::load_fleet_config(&fleet_path) {
+        Ok(config) => config,
+        Err(e) => panic!("VHAKM: Invalid fleet {}: {}", fleet_path, e),
+    };
 
-    web_socket.connect_multiple_streams(&endpoints()).unwrap();
+    for (family, bots) in config.families() {
+        println!("VHAKM: {} bots in family {}", bots.len(), family);
+    }
 
-    if let Err(e) = web_socket.event_loop(&keep_running) {
-        match e {
-            err => {
-                println!("Error: {:?}", err);
+    // the streams are subscribed once, symbols and intervals added by a reload need a restart.
+    let symbols = config.traded_symbols();
+    let intervals = config.subscribed_intervals();
+    let endpoints = endpoints(&config);
+
+    println!(
+        "VHAKM: Subscribing to {:?} klines of {:?}, aggregating {:?}, order books: {}, \
+         order flow: {}",
+        intervals,
+        symbols,
+        config.aggregated_intervals,
+        config.depth.is_some(),
+        config.order_flow.is_some()
+    );
+
+    let mut fleet = fleet::Fleet::new(config);
+
+    println!("VHAKM: Restored {} bots from redis", fleet.load_state());
+    let mut watcher = fleet::FleetWatcher::new(&fleet_path);
+
+    // market data of every symbol in redis, klines missed while the stream was down are fetched
+    // from the REST API.
+    let mut redis = redis::Client::open(simple_trading::REDIS_URL).unwrap();
+    let market: Market = Binance::new(None, None);
+
+    let mut runner = runner::Runner::new(fleet, symbols)
+        .with_hooks(&mut redis)
+        .with_market(market);
+
+    if let Ok(path) = env::var("EDDIE_RECORD") {
+        match replay::Recorder::open(&path) {
+            Ok(recorder) => {
+                println!("VHAKM: Recording the session to {}", path);
+                runner = runner.with_recorder(recorder);
             }
+            Err(e) => println!("VHAKM: Could not record to {}: {}", path, e),
         }
     }
 
-    web_socket.disconnect().unwrap();
-}
+    let mut source = sources::BinanceSource::new(endpoints);
+
+    let result = source.run(&keep_running, &mut |entry| {
+        if let Some(config) = watcher.poll() {
+            runner.reload(config);
+        }
+
+        runner.apply(entry);
+    });
 
-use simple_trading::SimpleTrading; // Assuming this is needed in your Rust code
-
-fn gemini_bots() -> Vec<SimpleTrading> {
-    let configs = vec![
-        (
-            "BTCUSDT:ScalperV1",
-            0.015 / 100.0,
-            5.0,
-            0.02,
-        ),
-        (
-            "BTCUSDT:DayTraderV2",
-            0.25 / 100.0,
-            15.0,
-            0.05,
-        ),
-        (
-            "BTCUSDT:SwingTraderV3",
-            0.75 / 100.0,
-            30.0,
-            0.10,
-        ),
-        (
-            "BTCUSDT:BreakoutHunterV4",
-            0.5 / 100.0,
-            20.0,
-            0.08,
-        ),
-        (
-            "BTCUSDT:ReversalCatcherV5",
-            0.3 / 100.0,
-            10.0,
-            0.03,
-        ),
-        (
-            "BTCUSDT:TrendFollowerV6",
-            1.0 / 100.0,
-            40.0,
-            0.12,
-        ),
-        (
-            "BTCUSDT:VolatilityPlayerV7",
-            0.6 / 100.0,
-            35.0,
-            0.15,
-        ),
-        (
-            "BTCUSDT:RangeBoundV8",
-            0.2 / 100.0,
-            12.0,
-            0.04,
-        ),
-        (
-            "BTCUSDT:MomentumTraderV9",
-            0.8 / 100.0,
-            25.0,
-            0.09,
-        ),
-        (
-            "BTCUSDT:MeanReversionV10",
-            0.18 / 100.0,
-            8.0,
-            0.025,
-        ),
-    ];
-
-    let trading_bots: Vec<SimpleTrading> = configs.into_iter().map(|config| {
-        SimpleTrading::new(
-            format!("gemini:{}", config.0.to_string()), // Name
-            0.0,                 // Starting price
-            config.1,             // Take profit percentage
-            config.2,             // Stop loss value in usd
-            config.3,             // Price moving in percentage
-        )
-    }).collect();
-
-    trading_bots
+    if let Err(e) = result {
+        println!("VHAKM: Market data failed: {}", e);
+    }
 }
@@ -0,0 +1,417 @@
+// Performance metrics of a bot from its closed trades, for the live ledgers and the backtests.
+// The equity curve is the starting balance plus the realized net P&L. Sharpe and Sortino use daily
+// equity returns (percentages with a balance, quote currency P&L without), annualized over 365 days.
+// `eddie metrics` recomputes them from the live ledgers into the `metrics` and
+// `metrics:symbol:{SYMBOL}` hashes, the bots do not reload their ledger on every closed trade.
+
+use crate::fleet;
+use crate::ledger::{self, TradeRecord};
+use crate::limits::DAY_MS;
+use crate::simple_trading::REDIS_URL;
+use redis::Commands;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+pub const METRICS_KEY: &str = "metrics";
+
+const PERIODS_PER_YEAR: f64 = 365.0;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Metrics {
+    pub from: i64,
+    pub to: i64,
+    pub trades: usize,
+    pub wins: usize,
+    pub losses: usize,
+    pub win_rate: f64,
+    pub gross_pnl: f64,
+    pub costs: f64,
+    pub net_pnl: f64,
+    // Net results, the average loss is a positive value.
+    pub average_win: f64,
+    pub average_loss: f64,
+    // Net wins over net losses, infinite (null in JSON) without losing trades.
+    pub profit_factor: f64,
+    // Average net P&L per trade.
+    pub expectancy: f64,
+    pub max_drawdown: f64,
+    // Longest time below a previous equity peak.
+    pub max_drawdown_duration_ms: i64,
+    // Annualized daily returns, infinite (null in JSON) for gains without variance or losing days.
+    pub sharpe: f64,
+    pub sortino: f64,
+    // Percentage of the period with an open position.
+    pub exposure: f64,
+}
+
+impl Metrics {
+    // Metrics of `trades` over `from`..`to` (ms), trades may come in any order.
+    pub fn new(trades: &[TradeRecord], starting_balance: f64, from: i64, to: i64) -> Self {
+        let mut trades = trades.to_vec();
+        trades.sort_by_key(|trade| trade.exit_time);
+
+        let won: Vec<f64> = trades.iter().map(|t| t.net_pnl).filter(|pnl| *pnl > 0.0).collect();
+        let lost: Vec<f64> = trades.iter().map(|t| -t.net_pnl).filter(|pnl| *pnl > 0.0).collect();
+        let (won_total, lost_total) = (total(won.iter().copied()), total(lost.iter().copied()));
+        let net_pnl = total(trades.iter().map(|trade| trade.net_pnl));
+        let (max_drawdown, max_drawdown_duration_ms) = drawdown(&trades, starting_balance, from, to);
+        let returns = daily_returns(&trades, starting_balance, from, to);
+
+        Self {
+            from,
+            to,
+            trades: trades.len(),
+            wins: won.len(),
+            losses: lost.len(),
+            win_rate: ratio(won.len() as f64, trades.len() as f64) * 100.0,
+            gross_pnl: total(trades.iter().map(|trade| trade.gross_pnl)),
+            costs: total(
+                trades
+                    .iter()
+                    .map(|trade| trade.fees + trade.slippage + trade.funding),
+            ),
+            net_pnl,
+            average_win: ratio(won_total, won.len() as f64),
+            average_loss: ratio(lost_total, lost.len() as f64),
+            profit_factor: if lost_total > 0.0 {
+                won_total / lost_total
+            } else {
+                f64::INFINITY
+            },
+            expectancy: ratio(net_pnl, trades.len() as f64),
+            max_drawdown,
+            max_drawdown_duration_ms,
+            sharpe: sharpe(&returns),
+            sortino: sortino(&returns),
+            exposure: exposure(&trades, from, to),
+        }
+    }
+
+    // Metrics of a ledger over the time it covers, from the first entry to the last exit.
+    pub fn from_ledger(trades: &[TradeRecord], starting_balance: f64) -> Self {
+        let from = trades.iter().map(|trade| trade.entry_time).min().unwrap_or_default();
+        let to = trades.iter().map(|trade| trade.exit_time).max().unwrap_or_default();
+
+        Self::new(trades, starting_balance, from, to)
+    }
+}
+
+// Sum starting from +0.0, an empty `Sum` of floats is -0.0.
+fn total(values: impl Iterator<Item = f64>) -> f64 {
+    values.fold(0.0, |total, value| total + value)
+}
+
+fn ratio(value: f64, count: f64) -> f64 {
+    if count > 0.0 {
+        value / count
+    } else {
+        0.0
+    }
+}
+
+// Max drawdown and its longest duration of the realized equity curve.
+fn drawdown(trades: &[TradeRecord], starting_balance: f64, from: i64, to: i64) -> (f64, i64) {
+    let mut equity = starting_balance;
+    let mut peak = starting_balance;
+    let mut peak_time = from;
+    let mut underwater = false;
+    let mut max_drawdown: f64 = 0.0;
+    let mut duration = 0;
+
+    for trade in trades {
+        equity += trade.net_pnl;
+
+        if equity >= peak {
+            if underwater {
+                duration = duration.max(trade.exit_time - peak_time);
+            }
+
+            peak = equity;
+            peak_time = trade.exit_time;
+            underwater = false;
+        } else {
+            underwater = true;
+            max_drawdown = max_drawdown.max(peak - equity);
+        }
+    }
+
+    if underwater {
+        duration = duration.max(to - peak_time);
+    }
+
+    (max_drawdown, duration)
+}
+
+// Equity change of every day of the period.
+fn daily_returns(trades: &[TradeRecord], starting_balance: f64, from: i64, to: i64) -> Vec<f64> {
+    let days = ((to - from) as f64 / DAY_MS as f64).ceil().max(1.0) as i64;
+    let mut returns = Vec::with_capacity(days as usize);
+    let mut trades = trades.iter().peekable();
+    let mut equity = starting_balance;
+
+    for day in 0..days {
+        let previous = equity;
+        let end = from + (day + 1) * DAY_MS;
+
+        while let Some(trade) = trades.next_if(|trade| trade.exit_time < end || day == days - 1) {
+            equity += trade.net_pnl;
+        }
+
+        returns.push(if starting_balance > 0.0 {
+            ratio(equity - previous, previous)
+        } else {
+            equity - previous
+        });
+    }
+
+    returns
+}
+
+fn mean(values: &[f64]) -> f64 {
+    ratio(total(values.iter().copied()), values.len() as f64)
+}
+
+// Mean return per unit of risk, annualized: infinite for gains without risk.
+fn annualized(mean: f64, risk: f64) -> f64 {
+    if risk > 0.0 {
+        mean / risk * PERIODS_PER_YEAR.sqrt()
+    } else if mean > 0.0 {
+        f64::INFINITY
+    } else {
+        0.0
+    }
+}
+
+fn sharpe(returns: &[f64]) -> f64 {
+    if returns.len() < 2 {
+        return 0.0;
+    }
+
+    let mean = mean(returns);
+    let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (returns.len() - 1) as f64;
+
+    annualized(mean, variance.sqrt())
+}
+
+// Like Sharpe, only the losing days count as risk.
+fn sortino(returns: &[f64]) -> f64 {
+    if returns.len() < 2 {
+        return 0.0;
+    }
+
+    let downside = returns.iter().map(|r| r.min(0.0).powi(2)).sum::<f64>() / returns.len() as f64;
+
+    annualized(mean(returns), downside.sqrt())
+}
+
+// Time covered by the trades, partial exits of one position overlap.
+fn exposure(trades: &[TradeRecord], from: i64, to: i64) -> f64 {
+    let mut spans: Vec<(i64, i64)> = trades
+        .iter()
+        .map(|trade| (trade.entry_time.max(from), trade.exit_time.min(to)))
+        .filter(|(entry, exit)| exit > entry)
+        .collect();
+    spans.sort();
+
+    let mut exposed = 0;
+    let mut covered = from;
+
+    for (entry, exit) in spans {
+        let start = entry.max(covered);
+
+        if exit > start {
+            exposed += exit - start;
+            covered = exit;
+        }
+    }
+
+    ratio(exposed as f64, (to - from) as f64) * 100.0
+}
+
+pub fn symbol_key(symbol: &str) -> String {
+    format!("{}:symbol:{}", METRICS_KEY, symbol)
+}
+
+pub fn store(
+    con: &mut redis::Connection,
+    bot: &str,
+    symbol: &str,
+    metrics: &Metrics,
+) -> redis::RedisResult<()> {
+    let value = serde_json::to_string(metrics).unwrap();
+
+    con.hset::<_, _, _, ()>(METRICS_KEY, bot, &value)?;
+    con.hset::<_, _, _, ()>(symbol_key(symbol), bot, &value)?;
+
+    Ok(())
+}
+
+pub fn print_table(rows: &[(String, Metrics)]) {
+    println!(
+        "{:<32} {:>6} {:>6} {:>9} {:>9} {:>6} {:>9} {:>10} {:>11} {:>10} {:>8} {:>7} {:>7} {:>6}",
+        "bot", "trades", "win %", "avg win", "avg loss", "pf", "expect", "costs", "net", "max dd", "dd h",
+        "sharpe", "sortino", "exp %"
+    );
+
+    for (bot, metrics) in rows {
+        println!(
+            "{:<32} {:>6} {:>6.1} {:>9.3} {:>9.3} {:>6.2} {:>9.3} {:>10.3} {:>11.3} {:>10.3} {:>8.1} {:>7.2} {:>7.2} {:>6.1}",
+            bot,
+            metrics.trades,
+            metrics.win_rate,
+            metrics.average_win,
+            metrics.average_loss,
+            metrics.profit_factor,
+            metrics.expectancy,
+            metrics.costs,
+            metrics.net_pnl,
+            metrics.max_drawdown,
+            metrics.max_drawdown_duration_ms as f64 / 3_600_000.0,
+            metrics.sharpe,
+            metrics.sortino,
+            metrics.exposure
+        );
+    }
+}
+
+// eddie metrics [--bot NAME | --symbol SYMBOL] [--fleet fleet.toml] [--from MS] [--to MS]:
+// metrics of the live ledgers, printed and stored in the metrics hashes.
+pub fn run_cli(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut bot = None;
+    let mut symbol = None;
+    let mut fleet_path = fleet::DEFAULT_FLEET_PATH.to_string();
+    let mut from = 0;
+    let mut to = i64::MAX;
+
+    let mut args = args.iter();
+
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", flag))?
+            .to_string();
+
+        match flag.as_str() {
+            "--bot" => bot = Some(value),
+            "--symbol" => symbol = Some(value.to_uppercase()),
+            "--fleet" => fleet_path = value,
+            "--from" => from = value.parse()?,
+            "--to" => to = value.parse()?,
+            _ => return Err(format!("unknown option {}", flag).into()),
+        }
+    }
+
+    // starting balances, bots no longer in the fleet count from zero.
+    let balances: BTreeMap<String, f64> = fleet::load_fleet_config(&fleet_path)
+        .map(|config| config.bots.into_iter().map(|bot| (bot.name, bot.balance)).collect())
+        .unwrap_or_default();
+
+    let mut con = redis::Client::open(REDIS_URL)?.get_connection()?;
+    let mut ledgers: BTreeMap<String, Vec<TradeRecord>> = BTreeMap::new();
+
+    let trades = match &symbol {
+        Some(symbol) => ledger::load_key(&mut con, &ledger::symbol_key(symbol), from, to)?,
+        None => ledger::load(&mut con, bot.as_deref(), from, to)?,
+    };
+
+    for trade in trades {
+        ledgers.entry(trade.bot.to_string()).or_default().push(trade);
+    }
+
+    let mut rows = Vec::new();
+
+    for (bot, trades) in ledgers {
+        let metrics = Metrics::from_ledger(&trades, balances.get(&bot).copied().unwrap_or_default());
+        store(&mut con, &bot, &trades[0].symbol, &metrics)?;
+        rows.push((bot, metrics));
+    }
+
+    print_table(&rows);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::{ExitReason, Side};
+
+    fn trade(entry_time: i64, exit_time: i64, net_pnl: f64) -> TradeRecord {
+        TradeRecord {
+            bot: "test:BTCUSDT:A".to_string(),
+            family: "vhakm".to_string(),
+            symbol: "BTCUSDT".to_string(),
+            side: Side::Long,
+            quantity: 1.0,
+            leverage: 1.0,
+            entry_price: 100_000.0,
+            entry_time,
+            exit_price: 100_000.0 + net_pnl + 1.0,
+            exit_time,
+            exit_reason: ExitReason::TakeProfit,
+            gross_pnl: net_pnl + 1.0,
+            fees: 1.0,
+            slippage: 0.0,
+            funding: 0.0,
+            net_pnl,
+            max_adverse_excursion: 0.0,
+            max_favourable_excursion: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_metrics() {
+        let hour = 3_600_000;
+
+        // +30 on day 1, -20 and -10 on day 2, +60 on day 3 and 4, out of order like a merged ledger.
+        let trades = [
+            trade(2 * DAY_MS, 2 * DAY_MS + hour, 60.0),
+            trade(0, 6 * hour, 30.0),
+            trade(DAY_MS, DAY_MS + hour, -20.0),
+            trade(DAY_MS + 6 * hour, DAY_MS + 12 * hour, -10.0),
+        ];
+
+        let metrics = Metrics::new(&trades, 1_000.0, 0, 4 * DAY_MS);
+
+        assert_eq!(metrics.trades, 4);
+        assert_eq!(metrics.wins, 2);
+        assert_eq!(metrics.win_rate, 50.0);
+        assert_eq!(metrics.net_pnl, 60.0);
+        assert_eq!(metrics.costs, 4.0);
+        assert_eq!(metrics.average_win, 45.0);
+        assert_eq!(metrics.average_loss, 15.0);
+        assert_eq!(metrics.profit_factor, 3.0);
+        assert_eq!(metrics.expectancy, 15.0);
+        assert_eq!(metrics.max_drawdown, 30.0);
+        // under the 1030 peak from 6h until the 60 win on day 3.
+        assert_eq!(metrics.max_drawdown_duration_ms, 2 * DAY_MS + hour - 6 * hour);
+        assert_eq!(metrics.exposure, 14.0 / 96.0 * 100.0);
+
+        // daily returns 3%, -2.91%, 6% and 0%.
+        assert!((metrics.sharpe - 7.573).abs() < 0.001, "{}", metrics.sharpe);
+        assert!(metrics.sortino > metrics.sharpe);
+
+        // no losing day: no downside, gains without variance have no risk at all.
+        let winning = [trade(0, hour, 30.0), trade(DAY_MS, DAY_MS + hour, 60.0)];
+        let winning = Metrics::new(&winning, 1_000.0, 0, 2 * DAY_MS);
+        assert!(winning.sharpe.is_finite() && winning.sharpe > 0.0);
+        assert_eq!(winning.sortino, f64::INFINITY);
+
+        let steady = [trade(0, hour, 10.0), trade(DAY_MS, DAY_MS + hour, 10.0)];
+        let steady = Metrics::new(&steady, 0.0, 0, 2 * DAY_MS);
+        assert_eq!((steady.sharpe, steady.sortino), (f64::INFINITY, f64::INFINITY));
+
+        let flat = Metrics::new(&[], 1_000.0, 0, 2 * DAY_MS);
+        assert_eq!((flat.sharpe, flat.sortino), (0.0, 0.0));
+
+        // a break-even trade is neither a win nor a loss.
+        let even = [trades[1].clone(), trade(DAY_MS, DAY_MS + hour, 0.0)];
+        let even = Metrics::new(&even, 0.0, 0, DAY_MS);
+        assert_eq!((even.trades, even.wins, even.losses), (2, 1, 0));
+        assert_eq!(even.profit_factor, f64::INFINITY);
+
+        let json = serde_json::to_string(&Metrics::from_ledger(&trades[1..2], 0.0)).unwrap();
+        assert!(json.contains(r#""profit_factor":null"#));
+        assert!(json.contains(r#""exposure":100.0"#));
+    }
+}
@@ -0,0 +1,385 @@
+// Local order book of a symbol, kept from the Binance diff depth stream ({symbol}@depth@100ms) and
+// REST snapshots. An update is applied when it follows the book (first update id <= last id + 1
+// < final update id), older ones are dropped and a missed one unsyncs the book until the next
+// snapshot, updates received meanwhile are replayed on it. Metrics of the book (spread, weighted
+// mid, volume and imbalance within bands around the mid price) are published to redis by Hooks.
+
+use binance::model::{DepthOrderBookEvent, OrderBook as Snapshot};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Levels of a REST snapshot.
+pub const SNAPSHOT_DEPTH: u64 = 1_000;
+
+// A failed or outdated snapshot is requested again after this much event time.
+pub const SNAPSHOT_RETRY_MS: i64 = 1_000;
+
+// Updates kept while waiting for a snapshot.
+const MAX_PENDING: usize = 1_000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DepthConfig {
+    // Bands around the mid price in percent, e.g. 0.1 sums the levels within 0.1% of the mid.
+    #[serde(default = "default_bands")]
+    pub bands: Vec<f64>,
+    // Metrics are published at most once per this many milliseconds of event time.
+    #[serde(default = "default_publish_ms")]
+    pub publish_ms: i64,
+    // Metrics older than this are trimmed from depth:{symbol}, 0 keeps them all.
+    #[serde(default = "default_retention_hours")]
+    pub retention_hours: f64,
+}
+
+fn default_bands() -> Vec<f64> {
+    vec![0.1, 0.5, 1.0]
+}
+
+fn default_publish_ms() -> i64 {
+    1_000
+}
+
+fn default_retention_hours() -> f64 {
+    24.0
+}
+
+impl Default for DepthConfig {
+    fn default() -> Self {
+        Self {
+            bands: default_bands(),
+            publish_ms: default_publish_ms(),
+            retention_hours: default_retention_hours(),
+        }
+    }
+}
+
+impl DepthConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.bands.is_empty() {
+            return Err("bands must not be empty".to_string());
+        }
+
+        if let Some(band) = self.bands.iter().find(|band| !band.is_finite() || **band <= 0.0) {
+            return Err(format!("bands must be positive percentages, got {}", band));
+        }
+
+        if self.publish_ms < 0 {
+            return Err(format!("publish_ms must be zero or positive, got {}", self.publish_ms));
+        }
+
+        if !self.retention_hours.is_finite() || self.retention_hours < 0.0 {
+            return Err(format!(
+                "retention_hours must be zero or positive, got {}",
+                self.retention_hours
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn retention_ms(&self) -> i64 {
+        (self.retention_hours * 3_600_000.0) as i64
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sync {
+    Applied,
+    // Kept until a snapshot.
+    Buffered,
+    // Already in the book.
+    Stale,
+    // Updates were missed, the book needs a new snapshot.
+    Gap,
+}
+
+// Volume of the levels within `band` percent of the mid price.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Liquidity {
+    pub band: f64,
+    pub bid_volume: f64,
+    pub ask_volume: f64,
+    // Volume weighted prices of the levels, 0 without volume.
+    pub weighted_bid: f64,
+    pub weighted_ask: f64,
+    // (bid - ask) / (bid + ask) volume, from -1 (only asks) to 1 (only bids).
+    pub imbalance: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DepthMetrics {
+    pub time: i64,
+    pub best_bid: f64,
+    pub best_ask: f64,
+    pub spread: f64,
+    pub mid: f64,
+    // Best prices weighted by the opposite quantities, it leans towards the ask when the best bid
+    // holds more.
+    pub weighted_mid: f64,
+    pub liquidity: Vec<Liquidity>,
+}
+
+// Prices are keyed in 1e-8, the smallest tick of the exchange.
+fn key(price: f64) -> i64 {
+    (price * 1e8).round() as i64
+}
+
+pub struct OrderBook {
+    pub symbol: String,
+    pub last_update_id: u64,
+    // Price and quantity by price key.
+    bids: BTreeMap<i64, (f64, f64)>,
+    asks: BTreeMap<i64, (f64, f64)>,
+    synced: bool,
+    pending: Vec<DepthOrderBookEvent>,
+    // Event times of the last snapshot request and publication.
+    pub requested: Option<i64>,
+    pub published: Option<i64>,
+}
+
+impl OrderBook {
+    pub fn new(symbol: &str) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            last_update_id: 0,
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+            synced: false,
+            pending: Vec::new(),
+            requested: None,
+            published: None,
+        }
+    }
+
+    pub fn synced(&self) -> bool {
+        self.synced
+    }
+
+    // Whether to request a snapshot at event time `time`.
+    pub fn wants_snapshot(&self, time: i64) -> bool {
+        !self.synced && self.requested.is_none_or(|requested| time - requested >= SNAPSHOT_RETRY_MS)
+    }
+
+    pub fn snapshot(&mut self, snapshot: Snapshot) {
+        self.bids = levels(snapshot.bids.iter().map(|level| (level.price, level.qty)));
+        self.asks = levels(snapshot.asks.iter().map(|level| (level.price, level.qty)));
+        self.last_update_id = snapshot.last_update_id;
+        self.synced = true;
+
+        for update in std::mem::take(&mut self.pending) {
+            self.update(update);
+        }
+    }
+
+    pub fn update(&mut self, update: DepthOrderBookEvent) -> Sync {
+        if !self.synced {
+            if self.pending.len() >= MAX_PENDING {
+                self.pending.remove(0);
+            }
+
+            self.pending.push(update);
+            return Sync::Buffered;
+        }
+
+        if update.final_update_id <= self.last_update_id {
+            return Sync::Stale;
+        }
+
+        if update.first_update_id > self.last_update_id + 1 {
+            self.synced = false;
+            self.pending = vec![update];
+            return Sync::Gap;
+        }
+
+        for level in &update.bids {
+            apply(&mut self.bids, level.price, level.qty);
+        }
+
+        for level in &update.asks {
+            apply(&mut self.asks, level.price, level.qty);
+        }
+
+        self.last_update_id = update.final_update_id;
+
+        Sync::Applied
+    }
+
+    pub fn best_bid(&self) -> Option<(f64, f64)> {
+        self.bids.values().next_back().copied()
+    }
+
+    pub fn best_ask(&self) -> Option<(f64, f64)> {
+        self.asks.values().next().copied()
+    }
+
+    // None until the book is synced with both sides.
+    pub fn metrics(&self, bands: &[f64], time: i64) -> Option<DepthMetrics> {
+        if !self.synced {
+            return None;
+        }
+
+        let (best_bid, bid_quantity) = self.best_bid()?;
+        let (best_ask, ask_quantity) = self.best_ask()?;
+        let mid = (best_bid + best_ask) / 2.0;
+
+        let weighted_mid = if bid_quantity + ask_quantity > 0.0 {
+            (best_bid * ask_quantity + best_ask * bid_quantity) / (bid_quantity + ask_quantity)
+        } else {
+            mid
+        };
+
+        let liquidity = bands
+            .iter()
+            .map(|band| {
+                let lowest = key(mid * (1.0 - band / 100.0));
+                let highest = key(mid * (1.0 + band / 100.0));
+
+                let (bid_volume, weighted_bid) = volume(self.bids.range(lowest..).map(|level| level.1));
+                let (ask_volume, weighted_ask) = volume(self.asks.range(..=highest).map(|level| level.1));
+
+                let total = bid_volume + ask_volume;
+
+                Liquidity {
+                    band: *band,
+                    bid_volume,
+                    ask_volume,
+                    weighted_bid,
+                    weighted_ask,
+                    imbalance: if total > 0.0 {
+                        (bid_volume - ask_volume) / total
+                    } else {
+                        0.0
+                    },
+                }
+            })
+            .collect();
+
+        Some(DepthMetrics {
+            time,
+            best_bid,
+            best_ask,
+            spread: best_ask - best_bid,
+            mid,
+            weighted_mid,
+            liquidity,
+        })
+    }
+
+    // Whether metrics are due at `time`, once per `publish_ms` of event time.
+    pub fn due(&mut self, time: i64, publish_ms: i64) -> bool {
+        if self.published.is_some_and(|published| time - published < publish_ms) {
+            return false;
+        }
+
+        self.published = Some(time);
+        true
+    }
+}
+
+fn levels(levels: impl Iterator<Item = (f64, f64)>) -> BTreeMap<i64, (f64, f64)> {
+    levels.filter(|(_, quantity)| *quantity > 0.0).map(|level| (key(level.0), level)).collect()
+}
+
+fn apply(side: &mut BTreeMap<i64, (f64, f64)>, price: f64, quantity: f64) {
+    if quantity > 0.0 {
+        side.insert(key(price), (price, quantity));
+    } else {
+        side.remove(&key(price));
+    }
+}
+
+// Total quantity and its volume weighted price.
+fn volume<'a>(levels: impl Iterator<Item = &'a (f64, f64)>) -> (f64, f64) {
+    let (quantity, notional) = levels.fold((0.0, 0.0), |(quantity, notional), (price, size)| {
+        (quantity + size, notional + price * size)
+    });
+
+    (quantity, if quantity > 0.0 { notional / quantity } else { 0.0 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use binance::model::{Asks, Bids};
+
+    fn ask(price: f64, qty: f64) -> Asks {
+        Asks { price, qty }
+    }
+
+    fn update(first: u64, last: u64, bids: &[(f64, f64)], asks: &[(f64, f64)]) -> DepthOrderBookEvent {
+        DepthOrderBookEvent {
+            event_type: "depthUpdate".to_string(),
+            event_time: last,
+            symbol: "BTCUSDT".to_string(),
+            first_update_id: first,
+            final_update_id: last,
+            previous_final_update_id: None,
+            bids: bids.iter().map(|(price, qty)| Bids::new(*price, *qty)).collect(),
+            asks: asks.iter().map(|(price, qty)| ask(*price, *qty)).collect(),
+        }
+    }
+
+    fn snapshot(last_update_id: u64) -> Snapshot {
+        Snapshot {
+            last_update_id,
+            bids: vec![Bids::new(99.0, 2.0), Bids::new(99.5, 1.0), Bids::new(90.0, 50.0)],
+            asks: vec![ask(100.5, 3.0), ask(100.0, 1.0), ask(110.0, 50.0)],
+        }
+    }
+
+    #[test]
+    fn test_sync() {
+        let mut book = OrderBook::new("BTCUSDT");
+
+        // updates received before the snapshot are replayed on it, the ones it holds dropped.
+        assert_eq!(book.update(update(5, 10, &[(99.5, 4.0)], &[])), Sync::Buffered);
+        assert_eq!(book.update(update(11, 12, &[], &[(100.0, 0.0)])), Sync::Buffered);
+        assert!(book.wants_snapshot(0));
+
+        book.snapshot(snapshot(10));
+        assert!(book.synced());
+        assert_eq!(book.last_update_id, 12);
+        assert_eq!(book.best_bid(), Some((99.5, 1.0)));
+        assert_eq!(book.best_ask(), Some((100.5, 3.0)));
+
+        assert_eq!(book.update(update(8, 12, &[], &[])), Sync::Stale);
+        assert_eq!(book.update(update(13, 13, &[(99.5, 0.0)], &[])), Sync::Applied);
+        assert_eq!(book.best_bid(), Some((99.0, 2.0)));
+
+        // a missed update unsyncs the book until the next snapshot.
+        assert_eq!(book.update(update(20, 21, &[(99.8, 1.0)], &[])), Sync::Gap);
+        assert!(!book.synced());
+        assert_eq!(book.metrics(&[1.0], 0), None);
+
+        book.requested = Some(1_000);
+        assert!(!book.wants_snapshot(1_500));
+        assert!(book.wants_snapshot(2_000));
+
+        book.snapshot(snapshot(20));
+        assert_eq!(book.best_bid(), Some((99.8, 1.0)));
+    }
+
+    #[test]
+    fn test_metrics() {
+        let mut book = OrderBook::new("BTCUSDT");
+        book.snapshot(snapshot(1));
+
+        let metrics = book.metrics(&[0.5, 20.0], 1_000).unwrap();
+        assert_eq!((metrics.best_bid, metrics.best_ask, metrics.spread), (99.5, 100.0, 0.5));
+        assert_eq!(metrics.mid, 99.75);
+        assert_eq!(metrics.weighted_mid, 99.75);
+
+        // within 0.5% of 99.75: bids from 99.25, asks up to 100.25.
+        let near = &metrics.liquidity[0];
+        assert_eq!((near.bid_volume, near.ask_volume), (1.0, 1.0));
+        assert_eq!(near.imbalance, 0.0);
+
+        let all = &metrics.liquidity[1];
+        assert_eq!((all.bid_volume, all.ask_volume), (53.0, 54.0));
+        assert_eq!(all.weighted_ask, (100.0 + 100.5 * 3.0 + 110.0 * 50.0) / 54.0);
+        assert!(all.imbalance < 0.0);
+
+        assert!(book.due(1_000, 1_000));
+        assert!(!book.due(1_900, 1_000));
+        assert!(book.due(2_000, 1_000));
+    }
+}
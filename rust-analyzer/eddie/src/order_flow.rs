@@ -0,0 +1,317 @@
+// Order flow of a symbol from the Binance aggregated trade stream ({symbol}@aggTrade). Kline
+// volume does not tell aggressive buyers from sellers, the trades do: a buyer maker trade was sold
+// by the taker. Over rolling windows of trade time the taker buy and sell volumes, their delta and
+// imbalance, the trade count, the VWAP and the large trades are published to redis by Hooks, next
+// to the price/volume variation indicators.
+
+use crate::strategy::interval_ms;
+use binance::model::AggrTradesEvent;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderFlowConfig {
+    // Rolling windows as kline intervals, e.g. "1m".
+    #[serde(default = "default_windows")]
+    pub windows: Vec<String>,
+    // Trades of at least this notional (price * quantity) are large, 0 turns the detection off.
+    #[serde(default)]
+    pub large_trade_notional: f64,
+    // Windows are published at most once per this many milliseconds of trade time.
+    #[serde(default = "default_publish_ms")]
+    pub publish_ms: i64,
+    // Windows older than this are trimmed from their sorted sets, 0 keeps them all.
+    #[serde(default = "default_retention_hours")]
+    pub retention_hours: f64,
+}
+
+fn default_windows() -> Vec<String> {
+    vec!["1m".to_string(), "5m".to_string(), "15m".to_string()]
+}
+
+fn default_publish_ms() -> i64 {
+    1_000
+}
+
+fn default_retention_hours() -> f64 {
+    24.0
+}
+
+impl Default for OrderFlowConfig {
+    fn default() -> Self {
+        Self {
+            windows: default_windows(),
+            large_trade_notional: 0.0,
+            publish_ms: default_publish_ms(),
+            retention_hours: default_retention_hours(),
+        }
+    }
+}
+
+impl OrderFlowConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.windows.is_empty() {
+            return Err("windows must not be empty".to_string());
+        }
+
+        if let Some(window) = self.windows.iter().find(|window| interval_ms(window).is_none()) {
+            return Err(format!("window {:?} is not an interval such as 1m", window));
+        }
+
+        if !self.large_trade_notional.is_finite() || self.large_trade_notional < 0.0 {
+            return Err(format!(
+                "large_trade_notional must be zero or positive, got {}",
+                self.large_trade_notional
+            ));
+        }
+
+        if self.publish_ms < 0 {
+            return Err(format!("publish_ms must be zero or positive, got {}", self.publish_ms));
+        }
+
+        if !self.retention_hours.is_finite() || self.retention_hours < 0.0 {
+            return Err(format!(
+                "retention_hours must be zero or positive, got {}",
+                self.retention_hours
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn retention_ms(&self) -> i64 {
+        (self.retention_hours * 3_600_000.0) as i64
+    }
+
+    // Length of the longest window, older trades are not needed anymore.
+    fn span(&self) -> i64 {
+        self.windows.iter().filter_map(|window| interval_ms(window)).max().unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trade {
+    pub time: i64,
+    pub price: f64,
+    pub quantity: f64,
+    // The taker sold into the bid.
+    pub buyer_maker: bool,
+}
+
+impl Trade {
+    // None when the price or quantity do not parse.
+    pub fn from_event(event: &AggrTradesEvent) -> Option<Self> {
+        Some(Self {
+            time: event.trade_order_time as i64,
+            price: event.price.parse().ok()?,
+            quantity: event.qty.parse().ok()?,
+            buyer_maker: event.is_buyer_maker,
+        })
+    }
+}
+
+// Sorted set of a window, next to the price/volume variations: variations:order_flow:BTCUSDT:1m.
+pub fn order_flow_key(symbol: &str, window: &str) -> String {
+    format!("variations:order_flow:{}:{}", symbol, window)
+}
+
+// Stored as JSON in the variations:order_flow:{symbol}:{window} sorted sets, scored by time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderFlow {
+    pub time: i64,
+    pub window: String,
+    pub trades: usize,
+    // Taker volumes in base currency.
+    pub buy_volume: f64,
+    pub sell_volume: f64,
+    // buy - sell volume.
+    pub delta: f64,
+    // delta / (buy + sell) volume, from -1 (only sellers) to 1 (only buyers).
+    pub imbalance: f64,
+    // 0 without trades.
+    pub vwap: f64,
+    pub large_trades: usize,
+    pub large_buy_volume: f64,
+    pub large_sell_volume: f64,
+}
+
+// Trades of one symbol within the longest window.
+pub struct TradeFlow {
+    pub symbol: String,
+    trades: VecDeque<Trade>,
+    // Trade time of the last publication.
+    pub published: Option<i64>,
+}
+
+impl TradeFlow {
+    pub fn new(symbol: &str) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            trades: VecDeque::new(),
+            published: None,
+        }
+    }
+
+    pub fn push(&mut self, trade: Trade, config: &OrderFlowConfig) {
+        let oldest = trade.time - config.span();
+
+        while self.trades.front().is_some_and(|first| first.time <= oldest) {
+            self.trades.pop_front();
+        }
+
+        self.trades.push_back(trade);
+    }
+
+    // Every window of `config` ending at `time`.
+    pub fn flows(&self, config: &OrderFlowConfig, time: i64) -> Vec<OrderFlow> {
+        config
+            .windows
+            .iter()
+            .filter_map(|window| {
+                let oldest = time - interval_ms(window)?;
+                let trades = self
+                    .trades
+                    .iter()
+                    .filter(|trade| trade.time > oldest && trade.time <= time);
+
+                Some(flow(window, time, trades, config.large_trade_notional))
+            })
+            .collect()
+    }
+
+    // Whether the windows are due at `time`, once per `publish_ms` of trade time.
+    pub fn due(&mut self, time: i64, publish_ms: i64) -> bool {
+        if self.published.is_some_and(|published| time - published < publish_ms) {
+            return false;
+        }
+
+        self.published = Some(time);
+        true
+    }
+}
+
+fn flow<'a>(
+    window: &str,
+    time: i64,
+    trades: impl Iterator<Item = &'a Trade>,
+    large_trade_notional: f64,
+) -> OrderFlow {
+    let mut flow = OrderFlow {
+        time,
+        window: window.to_string(),
+        trades: 0,
+        buy_volume: 0.0,
+        sell_volume: 0.0,
+        delta: 0.0,
+        imbalance: 0.0,
+        vwap: 0.0,
+        large_trades: 0,
+        large_buy_volume: 0.0,
+        large_sell_volume: 0.0,
+    };
+
+    let mut notional = 0.0;
+
+    for trade in trades {
+        let value = trade.price * trade.quantity;
+        let large = large_trade_notional > 0.0 && value >= large_trade_notional;
+
+        flow.trades += 1;
+        notional += value;
+
+        if trade.buyer_maker {
+            flow.sell_volume += trade.quantity;
+        } else {
+            flow.buy_volume += trade.quantity;
+        }
+
+        if large {
+            flow.large_trades += 1;
+
+            if trade.buyer_maker {
+                flow.large_sell_volume += trade.quantity;
+            } else {
+                flow.large_buy_volume += trade.quantity;
+            }
+        }
+    }
+
+    let volume = flow.buy_volume + flow.sell_volume;
+    flow.delta = flow.buy_volume - flow.sell_volume;
+
+    if volume > 0.0 {
+        flow.imbalance = flow.delta / volume;
+        flow.vwap = notional / volume;
+    }
+
+    flow
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(time: i64, price: f64, quantity: f64, buyer_maker: bool) -> Trade {
+        Trade {
+            time,
+            price,
+            quantity,
+            buyer_maker,
+        }
+    }
+
+    #[test]
+    fn test_trade_from_event() {
+        let payload = serde_json::json!({
+            "e": "aggTrade", "E": 1_500, "s": "BTCUSDT", "a": 1, "p": "100.5", "q": "0.25",
+            "f": 1, "l": 2, "T": 1_499, "m": true, "M": true
+        });
+
+        let event: AggrTradesEvent = serde_json::from_value(payload).unwrap();
+        assert_eq!(Trade::from_event(&event), Some(trade(1_499, 100.5, 0.25, true)));
+    }
+
+    #[test]
+    fn test_flows() {
+        let config = OrderFlowConfig {
+            windows: vec!["1s".to_string(), "1m".to_string()],
+            large_trade_notional: 1_000.0,
+            ..Default::default()
+        };
+
+        let mut flow = TradeFlow::new("BTCUSDT");
+
+        for trade in [
+            trade(0, 100.0, 1.0, false),
+            trade(59_000, 100.0, 20.0, true),
+            trade(60_000, 102.0, 2.0, false),
+            trade(60_200, 101.0, 1.0, true),
+        ] {
+            flow.push(trade, &config);
+        }
+
+        // the first trade left the longest window.
+        let flows = flow.flows(&config, 60_200);
+        assert_eq!(flows.len(), 2);
+
+        let second = &flows[0];
+        assert_eq!((second.trades, second.buy_volume, second.sell_volume), (2, 2.0, 1.0));
+        assert_eq!(second.delta, 1.0);
+        assert_eq!(second.imbalance, 1.0 / 3.0);
+        assert_eq!(second.vwap, (204.0 + 101.0) / 3.0);
+        assert_eq!(second.large_trades, 0);
+
+        let minute = &flows[1];
+        assert_eq!((minute.trades, minute.buy_volume, minute.sell_volume), (3, 2.0, 21.0));
+        assert!(minute.imbalance < 0.0);
+        assert_eq!((minute.large_trades, minute.large_sell_volume), (1, 20.0));
+
+        assert_eq!(TradeFlow::new("BTCUSDT").flows(&config, 0)[0].vwap, 0.0);
+
+        assert!(flow.due(60_200, 1_000));
+        assert!(!flow.due(61_000, 1_000));
+        assert!(flow.due(61_200, 1_000));
+
+        assert_eq!(order_flow_key("BTCUSDT", "1m"), "variations:order_flow:BTCUSDT:1m");
+    }
+}
@@ -1,125 +1,272 @@
 pub mod binance_hooks {
-    use crate::definitions::{IndicatorPeriods, TechnicalIndicatorPeriods};
-    use crate::utils::technical_indicators::technical_indicators::PriceVolume;
-    use binance::model::KlineEvent;
+    use crate::definitions::TechnicalIndicatorPeriods;
+    use crate::order_book::DepthMetrics;
+    use crate::order_flow::{order_flow_key, OrderFlow};
+    use crate::simple_trading::RECONNECT_DELAY;
+    use crate::strategy::{Kline, TICK_INTERVAL};
+    use crate::utils::technical_indicators::technical_indicators::{PriceVolume, VariationWriter};
     use redis::{Client, Commands};
     use serde::{Deserialize, Serialize};
+    use std::cell::{Cell, RefCell, RefMut};
     use std::collections::VecDeque;
-    use chrono::Utc;
-
-    #[derive(Debug, Serialize, Deserialize)]
-    struct KlineData {
-        open: String,
-        high: String,
-        low: String,
-        close: String,
-        volume: String,
-        close_time: i64,
+    use std::time::Instant;
+
+    // Stored as JSON in the klines:{symbol}:{interval} sorted sets, scored by close time: live,
+    // backfilled and imported klines share one score.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct KlineData {
+        pub open: String,
+        pub high: String,
+        pub low: String,
+        pub close: String,
+        pub volume: String,
+        pub close_time: i64,
     }
 
+    impl From<&Kline> for KlineData {
+        fn from(kline: &Kline) -> Self {
+            Self {
+                open: kline.open.to_string(),
+                high: kline.high.to_string(),
+                low: kline.low.to_string(),
+                close: kline.close.to_string(),
+                volume: kline.volume.to_string(),
+                close_time: kline.close_time,
+            }
+        }
+    }
+
+    // Market data of one symbol: closed klines of every interval, the last price and the
+    // price/volume variations of the 1s klines, scored by the same time as their kline.
     pub struct Hooks {
         symbol: String,
         redis: Client,
+        connection: RefCell<Option<redis::Connection>>,
+        // Set while redis is unreachable: no new connection is tried before then.
+        reconnect_at: Cell<Option<Instant>>,
         last_price: f64,
         ti: TechnicalIndicatorPeriods,
         prices: VecDeque<f64>,
         volumes: VecDeque<f64>,
+        variations: VariationWriter,
     }
 
     impl Hooks {
-        pub fn new(client: &mut Client, symbol: String) -> Self {
+        pub fn new(client: &mut Client, symbol: String, variations: VariationWriter) -> Self {
             let ti = TechnicalIndicatorPeriods::new();
 
             Self {
                 symbol,
                 ti: TechnicalIndicatorPeriods::new(),
                 redis: client.clone(),
+                connection: RefCell::new(None),
+                reconnect_at: Cell::new(None),
                 last_price: 0.0,
                 prices: VecDeque::with_capacity(ti.price_variation.long + 1),
                 volumes: VecDeque::with_capacity(ti.volume_variation.long + 1),
+                variations,
             }
         }
 
+        // The collector keeps going when redis is unreachable, an outage is reported once.
+        fn connection(&self) -> Option<RefMut<'_, redis::Connection>> {
+            let mut connection = self.connection.borrow_mut();
+
+            if connection.as_ref().is_some_and(|con| !redis::ConnectionLike::is_open(con)) {
+                *connection = None;
+            }
+
+            if connection.is_none() {
+                if self.reconnect_at.get().is_some_and(|at| Instant::now() < at) {
+                    return None;
+                }
+
+                match self.redis.get_connection() {
+                    Ok(con) => {
+                        if self.reconnect_at.take().is_some() {
+                            println!("{}, Redis available again", self.symbol);
+                        }
+
+                        *connection = Some(con);
+                    }
+                    Err(e) => {
+                        if self.reconnect_at.get().is_none() {
+                            println!("{}, Redis unavailable: {}", self.symbol, e);
+                        }
+
+                        self.reconnect_at.set(Some(Instant::now() + RECONNECT_DELAY));
+                        return None;
+                    }
+                }
+            }
+
+            RefMut::filter_map(connection, Option::as_mut).ok()
+        }
+
         fn save_data(&self, key: String, value: &String, score: u64) {
-            let mut con = self.redis.get_connection().unwrap();
+            if let Some(mut con) = self.connection() {
+                let result: redis::RedisResult<()> = con.zadd(key, value, score);
 
-            let _: () = con.zadd(key, value, score).unwrap();
+                if let Err(e) = result {
+                    println!("{}, Could not save kline: {}", self.symbol, e);
+                }
+            }
         }
 
         fn save_one(&self, key: String, field: &String, value: &String) {
-            let mut con = self.redis.get_connection().unwrap();
+            if let Some(mut con) = self.connection() {
+                let result: redis::RedisResult<()> = con.hset(key, field, value);
 
-            let _: () = con.hset(key, field, value).unwrap();
+                if let Err(e) = result {
+                    println!("{}, Could not save {}: {}", self.symbol, field, e);
+                }
+            }
         }
 
-        pub fn kline(&mut self, event: KlineEvent) {
-            if event.kline.is_final_bar == false {
+        // A kline update of the websocket, a backfilled kline or a locally aggregated candle,
+        // scored by its close time. Variations older than `retention_ms` are trimmed when positive.
+        pub fn kline(&mut self, kline: &Kline, retention_ms: i64) {
+            if !kline.closed || kline.symbol != self.symbol {
                 return;
             }
 
-            let score = Utc::now().timestamp() as u64;
-
+            if kline.interval == TICK_INTERVAL {
+                self.prices.push_front(kline.close);
+                self.volumes.push_front(kline.volume);
 
-            self.prices.push_front(event.kline.close.parse().unwrap());
-            self.volumes.push_front(event.kline.volume.parse().unwrap());
+                self.indicators(kline.close_time, retention_ms);
 
-            self.indicators(score);
-
-            if event.kline.interval == "1s" {
-                self.last_price = event.kline.close.parse().unwrap();
+                self.last_price = kline.close;
 
                 self.save_one(
                     "prices".to_string(),
-                    &event.symbol,
-                    &event.kline.close,
+                    &kline.symbol,
+                    &kline.close.to_string(),
                 );
             }
 
-            let key = format!("klines:{}:{}", event.symbol, event.kline.interval);
+            let key = format!("klines:{}:{}", kline.symbol, kline.interval);
+
+            let value = serde_json::to_string(&KlineData::from(kline)).unwrap(); // Serialize struct to JSON
+
+            self.save_data(key, &value, kline.close_time as u64);
+        }
+
+        // Order book metrics: the latest in the `depth` hash, the history in the depth:{symbol}
+        // sorted set scored by event time, trimmed to `retention_ms` when positive.
+        pub fn depth(&self, metrics: &DepthMetrics, retention_ms: i64) {
+            let Some(mut con) = self.connection() else {
+                return;
+            };
+
+            let key = format!("depth:{}", self.symbol);
+            let value = serde_json::to_string(metrics).unwrap();
+
+            let mut pipe = redis::pipe();
+            pipe.hset("depth", &self.symbol, &value).ignore();
+            pipe.zadd(&key, &value, metrics.time).ignore();
+
+            if retention_ms > 0 {
+                pipe.zrembyscore(&key, "-inf", metrics.time - retention_ms).ignore();
+            }
+
+            let result: redis::RedisResult<()> = pipe.query(&mut *con);
 
-            let kline = KlineData {
-                close: event.kline.close,
-                open: event.kline.open,
-                high: event.kline.high,
-                low: event.kline.low,
-                volume: event.kline.volume,
-                close_time: event.kline.close_time,
+            if let Err(e) = result {
+                println!("{}, Could not save depth: {}", self.symbol, e);
+            }
+        }
+
+        // Order flow windows next to the price/volume variations, in the
+        // variations:order_flow:{symbol}:{window} sorted sets scored by trade time, trimmed to
+        // `retention_ms` when positive.
+        pub fn order_flow(&self, flows: &[OrderFlow], retention_ms: i64) {
+            let Some(mut con) = self.connection() else {
+                return;
             };
 
-            let value = serde_json::to_string(&kline).unwrap(); // Serialize struct to JSON
+            let mut pipe = redis::pipe();
 
-            self.save_data(key, &value, event.event_time);
+            for flow in flows {
+                let key = order_flow_key(&self.symbol, &flow.window);
+                let value = serde_json::to_string(flow).unwrap();
+
+                pipe.zadd(&key, &value, flow.time).ignore();
+
+                if retention_ms > 0 {
+                    pipe.zrembyscore(&key, "-inf", flow.time - retention_ms).ignore();
+                }
+            }
+
+            let result: redis::RedisResult<()> = pipe.query(&mut *con);
+
+            if let Err(e) = result {
+                println!("{}, Could not save order flow: {}", self.symbol, e);
+            }
         }
 
-        fn indicators(&mut self, score: u64) {
+        fn indicators(&mut self, time: i64, retention_ms: i64) {
             if self.prices.len() > self.ti.price_variation.long {
                 // remove last element of prices
                 self.prices.pop_back();
                 self.volumes.pop_back();
 
-                let mut client = self.redis.clone();
-
-                let pv = PriceVolume {};
+                let pv = PriceVolume { retention_ms };
 
                 pv.process_prices(
                     self.symbol.to_string(),
                     Vec::from(self.prices.clone()),
                     self.ti.price_variation.all_periods(),
-                    &mut client,
-                    score,
+                    &self.variations,
+                    time,
                 );
 
                 pv.process_volumes(
                     self.symbol.to_string(),
                     Vec::from(self.volumes.clone()),
                     self.ti.volume_variation.all_periods(),
-                    &mut client,
-                    score,
+                    &self.variations,
+                    time,
                 );
-
             }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_kline_data_round_trip() {
+            let kline = Kline {
+                symbol: "ETHUSDT".to_string(),
+                interval: "5m".to_string(),
+                open_time: 300_000,
+                close_time: 599_999,
+                open: 3_456.78,
+                high: 3_460.0,
+                low: 3_450.5,
+                close: 3_458.3,
+                volume: 12.5,
+                closed: true,
+            };
+
+            let data = KlineData::from(&kline);
+            assert_eq!(data.close, "3458.3");
+            assert_eq!(Kline::from_data("ETHUSDT", "5m", &data), Some(kline));
+        }
 
+        #[test]
+        fn test_connection_retry() {
+            // nothing listens there: the failure is remembered, not retried on the next kline.
+            let mut client = Client::open("redis://127.0.0.1:1").unwrap();
+            let variations = VariationWriter::new(&client);
+            let hooks = Hooks::new(&mut client, "BTCUSDT".to_string(), variations);
 
+            assert!(hooks.connection().is_none());
+            let reconnect_at = hooks.reconnect_at.get().unwrap();
+            assert!(hooks.connection().is_none());
+            assert_eq!(hooks.reconnect_at.get(), Some(reconnect_at));
         }
     }
 }
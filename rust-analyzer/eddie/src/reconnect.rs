@@ -0,0 +1,178 @@
+// Recovery of the websocket streams. A dropped connection is retried with an exponential backoff,
+// and the klines missed meanwhile are detected from the open times of the next ones and fetched
+// from the REST API (see Runner::event), so the klines:* sorted sets and the aggregated candles
+// have no hole when the bots resume.
+
+use crate::strategy::{interval_ms, Kline};
+use binance::market::Market;
+use binance::model::{KlineSummaries, KlineSummary};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::time::Duration;
+
+pub const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+pub const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+// A connection that lasted this long resets the backoff.
+pub const STABLE_CONNECTION: Duration = Duration::from_secs(60);
+
+// Klines per REST request, the API maximum.
+const PAGE_SIZE: u16 = 1_000;
+
+// Longer gaps are only backfilled with their most recent klines, a day of 1s klines would take 87
+// requests while the bots wait.
+pub const MAX_BACKFILL: i64 = 10_000;
+
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            current: initial,
+        }
+    }
+
+    // Delay before the next attempt, doubled on every call up to `max`.
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.current;
+        self.current = (self.current * 2).min(self.max);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.current = self.initial;
+    }
+}
+
+// End of the last kline seen on every symbol and interval.
+#[derive(Default)]
+pub struct Gaps {
+    last: HashMap<(String, String), i64>,
+}
+
+impl Gaps {
+    // Open times missing before `kline`, the first kline of a stream starts the tracking.
+    pub fn check(&mut self, kline: &Kline) -> Option<Range<i64>> {
+        // updates of the open bar only cover the time before it.
+        let end = if kline.closed {
+            kline.close_time
+        } else {
+            kline.open_time - 1
+        };
+
+        let last = self
+            .last
+            .entry((kline.symbol.to_string(), kline.interval.to_string()))
+            .or_insert(end);
+
+        let gap = (kline.open_time > *last + 1).then(|| *last + 1..kline.open_time);
+        *last = (*last).max(end);
+
+        gap
+    }
+}
+
+fn kline(symbol: &str, interval: &str, summary: &KlineSummary) -> Kline {
+    Kline {
+        symbol: symbol.to_string(),
+        interval: interval.to_string(),
+        open_time: summary.open_time,
+        close_time: summary.close_time,
+        open: summary.open.parse().unwrap_or_default(),
+        high: summary.high.parse().unwrap_or_default(),
+        low: summary.low.parse().unwrap_or_default(),
+        close: summary.close.parse().unwrap_or_default(),
+        volume: summary.volume.parse().unwrap_or_default(),
+        closed: true,
+    }
+}
+
+// Closed klines opened during `gap`, oldest first.
+pub fn fetch(
+    market: &Market,
+    symbol: &str,
+    interval: &str,
+    gap: Range<i64>,
+) -> Result<Vec<Kline>, String> {
+    let length = interval_ms(interval).ok_or(format!("unknown interval {}", interval))?;
+    let mut start = gap.start.max(gap.end - MAX_BACKFILL * length);
+    let mut klines = Vec::new();
+
+    if start > gap.start {
+        println!(
+            "VHAKM: {} {} gap too long, backfilling from {} only",
+            symbol, interval, start
+        );
+    }
+
+    while start < gap.end {
+        let KlineSummaries::AllKlineSummaries(page) = market
+            .get_klines(symbol, interval, PAGE_SIZE, start as u64, (gap.end - 1) as u64)
+            .map_err(|e| e.to_string())?;
+
+        let Some(last) = page.last() else {
+            break;
+        };
+
+        start = last.close_time + 1;
+        klines.extend(page.iter().map(|summary| kline(symbol, interval, summary)));
+    }
+
+    Ok(klines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kline(interval: &str, open_time: i64, length: i64, closed: bool) -> Kline {
+        Kline {
+            symbol: "BTCUSDT".to_string(),
+            interval: interval.to_string(),
+            open_time,
+            close_time: open_time + length - 1,
+            open: 100.0,
+            high: 100.0,
+            low: 100.0,
+            close: 100.0,
+            volume: 1.0,
+            closed,
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        let mut backoff = Backoff::new(INITIAL_BACKOFF, MAX_BACKOFF);
+        let delays: Vec<u64> = (0..8).map(|_| backoff.next_delay().as_secs()).collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 16, 32, 60, 60]);
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), INITIAL_BACKOFF);
+    }
+
+    #[test]
+    fn test_gaps() {
+        let mut gaps = Gaps::default();
+
+        assert_eq!(gaps.check(&kline("1s", 10_000, 1_000, true)), None);
+        assert_eq!(gaps.check(&kline("1s", 11_000, 1_000, true)), None);
+
+        // 12s to 14s were missed.
+        assert_eq!(gaps.check(&kline("1s", 15_000, 1_000, true)), Some(12_000..15_000));
+        assert_eq!(gaps.check(&kline("1s", 15_000, 1_000, true)), None);
+        assert_eq!(gaps.check(&kline("1s", 16_000, 1_000, true)), None);
+
+        // updates of an open minute are not gaps, the minutes missed before it are.
+        assert_eq!(gaps.check(&kline("1m", 0, 60_000, true)), None);
+        assert_eq!(gaps.check(&kline("1m", 180_000, 60_000, false)), Some(60_000..180_000));
+        assert_eq!(gaps.check(&kline("1m", 180_000, 60_000, false)), None);
+        assert_eq!(gaps.check(&kline("1m", 180_000, 60_000, true)), None);
+        assert_eq!(gaps.check(&kline("1m", 240_000, 60_000, false)), None);
+    }
+}
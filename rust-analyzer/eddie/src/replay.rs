@@ -0,0 +1,357 @@
+// Recording of the market data seen by the live bots and its replay through the same Runner, to
+// reproduce a production session offline. A recording is an append-only JSON lines file (set
+// EDDIE_RECORD to its path): the fleet and the bot states restored when a process starts, every
+// fleet reload, every websocket event as received, the klines backfilled after a gap and the
+// order book snapshots, each with the wall clock time it was received at.
+
+use crate::fleet::{Fleet, FleetConfig};
+use crate::metrics;
+use crate::runner::Runner;
+use crate::simple_trading::SimpleTradingState;
+use crate::sources::{self, MarketSource};
+use crate::strategy::Kline;
+use binance::model::OrderBook;
+use binance::websockets::WebsocketEvent;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, LineWriter, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+// Events are the bulk of a recording, boxing them would not save anything.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Entry {
+    // A new process with this fleet, the states it restored follow.
+    Start(FleetConfig),
+    States(BTreeMap<String, SimpleTradingState>),
+    Fleet(FleetConfig),
+    Event(WebsocketEvent),
+    Backfill(Vec<Kline>),
+    // Order book snapshot fetched for the depth stream of `symbol`.
+    Snapshot { symbol: String, book: OrderBook },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    // Wall clock time in milliseconds.
+    pub received: i64,
+    #[serde(flatten)]
+    pub entry: Entry,
+}
+
+pub struct Recorder {
+    path: String,
+    file: LineWriter<File>,
+}
+
+impl Recorder {
+    pub fn open(path: &str) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(Self {
+            path: path.to_string(),
+            file: LineWriter::new(file),
+        })
+    }
+
+    // The bots keep going when the recording fails.
+    pub fn record(&mut self, entry: Entry) {
+        let record = Record {
+            received: Utc::now().timestamp_millis(),
+            entry,
+        };
+
+        let line = serde_json::to_string(&record).unwrap();
+
+        if let Err(e) = writeln!(self.file, "{}", line) {
+            println!("VHAKM: Could not record to {}: {}", self.path, e);
+        }
+    }
+}
+
+pub fn load(path: &str) -> Result<Vec<Record>, Box<dyn std::error::Error>> {
+    let mut records = Vec::new();
+
+    for (index, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let record = serde_json::from_str(&line)
+            .map_err(|e| format!("{} line {}: {}", path, index + 1, e))?;
+
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Speed {
+    // Multiple of the recorded pace, 1 is real time.
+    Pace(f64),
+    Max,
+}
+
+impl Speed {
+    // "max", "real" or a multiple such as "10" or "10x".
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "max" => Ok(Speed::Max),
+            "real" => Ok(Speed::Pace(1.0)),
+            _ => match value.trim_end_matches('x').parse::<f64>() {
+                Ok(pace) if pace > 0.0 && pace.is_finite() => Ok(Speed::Pace(pace)),
+                _ => Err(format!("speed must be max, real or a positive multiple, got {}", value)),
+            },
+        }
+    }
+
+    // Wait before an entry received `elapsed` milliseconds after the previous one.
+    pub fn delay(&self, elapsed: i64) -> Option<Duration> {
+        match self {
+            Speed::Pace(pace) if elapsed > 0 => {
+                Some(Duration::from_secs_f64(elapsed as f64 / 1_000.0 / pace))
+            }
+            _ => None,
+        }
+    }
+}
+
+// Entries of a recording at the recorded pace. The recorded fleets are replaced by `fleet` when
+// given, to replay the session with other settings.
+pub struct ReplaySource {
+    pub records: Vec<Record>,
+    pub speed: Speed,
+    pub fleet: Option<FleetConfig>,
+}
+
+impl MarketSource for ReplaySource {
+    fn run(&mut self, running: &AtomicBool, handle: &mut dyn FnMut(Entry)) -> Result<(), String> {
+        let mut previous: Option<i64> = None;
+
+        for record in self.records.drain(..) {
+            if !running.load(Ordering::Relaxed) {
+                break;
+            }
+
+            let pause = previous.and_then(|previous| self.speed.delay(record.received - previous));
+
+            if let Some(delay) = pause {
+                std::thread::sleep(delay);
+            }
+
+            previous = Some(record.received);
+
+            match (record.entry, &self.fleet) {
+                (Entry::Start(_), Some(fleet)) => handle(Entry::Start(fleet.clone())),
+                (Entry::Fleet(_), Some(_)) => {}
+                (entry, _) => handle(entry),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// eddie replay --file session.jsonl [--speed max|real|10x] [--fleet fleet.toml]
+pub fn run_cli(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut path = None;
+    let mut speed = Speed::Max;
+    let mut fleet_path = None;
+
+    let mut args = args.iter();
+
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", flag))?
+            .to_string();
+
+        match flag.as_str() {
+            "--file" => path = Some(value),
+            "--speed" => speed = Speed::parse(&value)?,
+            "--fleet" => fleet_path = Some(value),
+            _ => return Err(format!("unknown option {}", flag).into()),
+        }
+    }
+
+    let path = path.ok_or("--file is required")?;
+    let fleet = fleet_path.map(|path| crate::fleet::load_fleet_config(&path)).transpose()?;
+
+    let records = load(&path)?;
+    println!("VHAKM: Replaying {} records of {} at {:?}", records.len(), path, speed);
+
+    // the bots write nothing to redis, the first Start entry sets the fleet.
+    let config = fleet.clone().unwrap_or_default();
+    let symbols = config.traded_symbols();
+    let mut runner = Runner::new(Fleet::new(config), symbols).without_persistence();
+
+    let mut source = ReplaySource {
+        records,
+        speed,
+        fleet,
+    };
+
+    let session = sources::drive(&mut source, &mut runner)?;
+
+    println!("VHAKM: {} events replayed", session.events);
+    metrics::print_table(&session.metrics());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::TradeRecord;
+
+    const FLEET: &str = r#"
+        [[bots]]
+        name = "test:BTCUSDT:A"
+        family = "vhakm"
+        symbol = "BTCUSDT"
+        take_profit_percentage = 0.03
+        stop_loss_usd = 5.0
+        watch_movement_percentage = 4.0
+    "#;
+
+    fn event(second: u64, close: f64) -> WebsocketEvent {
+        let payload = serde_json::json!({
+            "e": "kline", "E": second * 1_000 + 1_000, "s": "BTCUSDT",
+            "k": {
+                "t": second * 1_000, "T": second * 1_000 + 999, "s": "BTCUSDT", "i": "1s",
+                "f": 1, "L": 2, "o": close.to_string(), "c": close.to_string(),
+                "h": close.to_string(), "l": close.to_string(), "v": "1.0", "n": 2, "x": true,
+                "q": "0", "V": "0", "Q": "0", "B": "0"
+            }
+        });
+
+        WebsocketEvent::Kline(serde_json::from_value(payload).unwrap())
+    }
+
+    #[test]
+    fn test_speed() {
+        assert_eq!(Speed::parse("max"), Ok(Speed::Max));
+        assert_eq!(Speed::parse("real"), Ok(Speed::Pace(1.0)));
+        assert_eq!(Speed::parse("10x"), Ok(Speed::Pace(10.0)));
+        assert!(Speed::parse("0").is_err());
+
+        assert_eq!(Speed::Pace(10.0).delay(2_000), Some(Duration::from_millis(200)));
+        assert_eq!(Speed::Max.delay(2_000), None);
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let path = std::env::temp_dir().join("eddie_replay_session.jsonl");
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        let config: FleetConfig = config::Config::builder()
+            .add_source(config::File::from_str(FLEET, config::FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        // the live session, a long after 100042 and its take profit.
+        let symbols = config.traded_symbols();
+        let mut runner = Runner::new(Fleet::new(config), symbols)
+            .without_persistence()
+            .with_recorder(Recorder::open(path).unwrap());
+
+        let mut live = Vec::new();
+
+        for (second, close) in [100_000.0, 100_010.0, 100_042.0, 100_042.0, 100_080.0, 100_150.0]
+            .into_iter()
+            .enumerate()
+        {
+            live.extend(runner.event(event(second as u64, close)));
+        }
+
+        drop(runner);
+
+        let records = load(path).unwrap();
+        assert_eq!(records.len(), 8);
+        assert!(matches!(records[0].entry, Entry::Start(_)));
+
+        let mut replay = Runner::new(Fleet::new(FleetConfig::default()), Default::default())
+            .without_persistence();
+
+        let mut source = ReplaySource {
+            records,
+            speed: Speed::Max,
+            fleet: None,
+        };
+
+        let session = sources::drive(&mut source, &mut replay).unwrap();
+        assert_eq!(session.events, 6);
+        assert_eq!((session.from, session.to), (1_000, 6_000));
+
+        let trades: Vec<TradeRecord> = live
+            .into_iter()
+            .filter_map(|(_, intent)| intent.trade())
+            .collect();
+
+        assert_eq!(trades.len(), 1);
+        assert_eq!(session.trades["test:BTCUSDT:A"], trades);
+    }
+
+    #[test]
+    fn test_replayed_removal() {
+        let path = std::env::temp_dir().join("eddie_replay_removal.jsonl");
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        let config: FleetConfig = config::Config::builder()
+            .add_source(config::File::from_str(FLEET, config::FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        // a long after 100042, still open when the bot is removed from the fleet.
+        let symbols = config.traded_symbols();
+        let mut runner = Runner::new(Fleet::new(config), symbols)
+            .without_persistence()
+            .with_recorder(Recorder::open(path).unwrap());
+
+        for (second, close) in [100_000.0, 100_010.0, 100_042.0, 100_042.0, 100_050.0]
+            .into_iter()
+            .enumerate()
+        {
+            assert!(runner
+                .event(event(second as u64, close))
+                .into_iter()
+                .all(|(_, intent)| intent.trade().is_none()));
+        }
+
+        let closed = runner.apply(Entry::Fleet(FleetConfig::default()));
+        drop(runner);
+
+        let trades: Vec<TradeRecord> = closed
+            .into_iter()
+            .filter_map(|(_, intent)| intent.trade())
+            .collect();
+
+        assert_eq!(trades.len(), 1);
+        assert_eq!((trades[0].exit_price, trades[0].exit_time), (100_050.0, 5_000));
+
+        let mut replay = Runner::new(Fleet::new(FleetConfig::default()), Default::default())
+            .without_persistence();
+
+        let mut source = ReplaySource {
+            records: load(path).unwrap(),
+            speed: Speed::Max,
+            fleet: None,
+        };
+
+        let session = sources::drive(&mut source, &mut replay).unwrap();
+        assert_eq!(session.trades["test:BTCUSDT:A"], trades);
+    }
+}
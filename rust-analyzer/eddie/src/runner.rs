@@ -0,0 +1,401 @@
+// The live pipeline behind every market data source: each kline goes through `Runner::event`,
+// which fills the gaps of its stream, dispatches it to the fleet, aggregates candles and stores
+// the market data, depth updates keep the order book of their symbol and aggregated trades its
+// order flow. The REST backfills and snapshots run on a worker (see `fetcher`), the klines of a
+// stream are held until its backfill is applied. The Binance streams, recordings and synthetic
+// feeds all drive a runner (see `sources`).
+
+use crate::candles::Candles;
+use crate::fetcher::{Fetched, Fetcher, Request};
+use crate::fleet::{Fleet, FleetConfig};
+use crate::order_book::{OrderBook, Sync};
+use crate::order_flow::{Trade, TradeFlow};
+use crate::processors::binance_hooks::binance_hooks::Hooks;
+use crate::reconnect::Gaps;
+use crate::replay::{Entry, Recorder};
+use crate::simple_trading::SimpleTradingState;
+use crate::strategy::{self, Intent, Kline};
+use crate::utils::technical_indicators::technical_indicators::VariationWriter;
+use binance::market::Market;
+use binance::model::{self, AggrTradesEvent, DepthOrderBookEvent, KlineEvent};
+use binance::websockets::WebsocketEvent;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+pub struct Runner {
+    pub fleet: Fleet,
+    // Symbols of the subscribed streams.
+    pub symbols: BTreeSet<String>,
+    candles: Candles,
+    hooks: HashMap<String, Hooks>,
+    gaps: Gaps,
+    books: HashMap<String, OrderBook>,
+    flows: HashMap<String, TradeFlow>,
+    // Missed klines and snapshots are fetched from the REST API, replays use the recorded ones.
+    fetcher: Option<Fetcher>,
+    // Klines of the streams waiting for their backfill, by symbol and interval.
+    held: HashMap<(String, String), Vec<KlineEvent>>,
+    recorder: Option<Recorder>,
+    // Off in replays: the bots write nothing to redis.
+    persist: bool,
+}
+
+impl Runner {
+    pub fn new(fleet: Fleet, symbols: BTreeSet<String>) -> Self {
+        let candles = Candles::new(strategy::TICK_INTERVAL, &fleet.config.aggregated_intervals);
+
+        Self {
+            fleet,
+            symbols,
+            candles,
+            hooks: HashMap::new(),
+            gaps: Gaps::default(),
+            books: HashMap::new(),
+            flows: HashMap::new(),
+            fetcher: None,
+            held: HashMap::new(),
+            recorder: None,
+            persist: true,
+        }
+    }
+
+    // Market data of every symbol in redis: klines, last prices and price/volume variations.
+    pub fn with_hooks(mut self, redis: &mut redis::Client) -> Self {
+        let variations = VariationWriter::new(redis);
+
+        self.hooks = self
+            .symbols
+            .iter()
+            .map(|symbol| {
+                (symbol.to_string(), Hooks::new(redis, symbol.to_string(), variations.clone()))
+            })
+            .collect();
+        self
+    }
+
+    pub fn with_market(self, market: Market) -> Self {
+        self.with_fetcher(Fetcher::binance(market))
+    }
+
+    pub fn with_fetcher(mut self, fetcher: Fetcher) -> Self {
+        self.fetcher = Some(fetcher);
+        self
+    }
+
+    // Records the fleet, the restored bots and every event that follows.
+    pub fn with_recorder(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self.record(Entry::Start(self.fleet.config.clone()));
+        self.record(Entry::States(
+            self.fleet.bots.iter().map(|bot| (bot.name.to_string(), bot.snapshot())).collect(),
+        ));
+        self
+    }
+
+    pub fn without_persistence(mut self) -> Self {
+        self.persist = false;
+        self.detach();
+        self
+    }
+
+    fn detach(&mut self) {
+        if !self.persist {
+            for bot in &mut self.fleet.bots {
+                bot.persist = false;
+            }
+        }
+    }
+
+    fn record(&mut self, entry: Entry) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(entry);
+        }
+    }
+
+    // What a source emitted, returns the intents of the bots.
+    pub fn apply(&mut self, entry: Entry) -> Vec<(String, Intent)> {
+        match entry {
+            Entry::Start(config) => self.restart(config),
+            Entry::States(states) => self.restore(states),
+            Entry::Fleet(config) => return self.reload(config),
+            Entry::Backfill(klines) => {
+                self.record(Entry::Backfill(klines.clone()));
+                self.backfill(&klines);
+            }
+            Entry::Snapshot { symbol, book } => self.snapshot(symbol, book),
+            Entry::Event(event) => return self.event(event),
+        }
+
+        Vec::new()
+    }
+
+    // A new process: fresh bots, candles, gaps, books, flows and held klines for the streams of
+    // `config`.
+    pub fn restart(&mut self, config: FleetConfig) {
+        self.record(Entry::Start(config.clone()));
+
+        self.symbols = config.traded_symbols();
+        self.candles = Candles::new(strategy::TICK_INTERVAL, &config.aggregated_intervals);
+        self.gaps = Gaps::default();
+        self.books = HashMap::new();
+        self.flows = HashMap::new();
+        self.held = HashMap::new();
+        self.fleet = Fleet::new(config);
+        self.detach();
+    }
+
+    // Bot states saved by a previous process.
+    pub fn restore(&mut self, states: BTreeMap<String, SimpleTradingState>) {
+        for bot in &mut self.fleet.bots {
+            if let Some(state) = states.get(&bot.name) {
+                bot.restore(state.clone());
+            }
+        }
+
+        self.record(Entry::States(states));
+    }
+
+    // Apply a new fleet definition to the running bots, returns the positions closed by the
+    // `close` policy.
+    pub fn reload(&mut self, config: FleetConfig) -> Vec<(String, Intent)> {
+        self.record(Entry::Fleet(config.clone()));
+
+        let changes = self.fleet.apply(config);
+        self.detach();
+
+        if !changes.is_empty() {
+            println!(
+                "VHAKM: Fleet reloaded, added: {:?}, removed: {:?}, retuned: {:?}",
+                changes.added, changes.removed, changes.retuned
+            );
+        }
+
+        let missing: Vec<String> = self
+            .fleet
+            .config
+            .traded_symbols()
+            .difference(&self.symbols)
+            .cloned()
+            .collect();
+
+        if !missing.is_empty() {
+            println!("VHAKM: {:?} not subscribed, restart eddie to trade them", missing);
+        }
+
+        changes.closed.into_iter().map(|(name, trade)| (name, Intent::Close(trade))).collect()
+    }
+
+    // Closed klines missed by the stream, stored with the candles they complete. The bots do not
+    // trade them.
+    pub fn backfill(&mut self, klines: &[Kline]) {
+        let retention_ms = self.fleet.config.variations.retention_ms();
+
+        for kline in klines {
+            self.gaps.check(kline);
+
+            let aggregated = self.candles.push(kline);
+
+            if let Some(hooks) = self.hooks.get_mut(&kline.symbol) {
+                for kline in std::iter::once(kline).chain(&aggregated) {
+                    hooks.kline(kline, retention_ms);
+                }
+            }
+        }
+    }
+
+    // Returns the intents of the bots, with the name of the bot that emitted them.
+    pub fn event(&mut self, event: WebsocketEvent) -> Vec<(String, Intent)> {
+        let mut intents = self.fetched();
+
+        match event {
+            WebsocketEvent::Kline(event) => intents.extend(self.kline(event)),
+            WebsocketEvent::DepthOrderBook(update) => self.depth(update),
+            WebsocketEvent::AggrTrades(trade) => self.trade(trade),
+            _ => {
+                println!("Received event: {:?}", event);
+                self.record(Entry::Event(event));
+            }
+        }
+
+        intents
+    }
+
+    // Results of the REST worker, a backfill releases the klines held for its stream.
+    fn fetched(&mut self) -> Vec<(String, Intent)> {
+        let Some(fetcher) = &self.fetcher else {
+            return Vec::new();
+        };
+
+        let mut intents = Vec::new();
+
+        for fetched in fetcher.fetched() {
+            match fetched {
+                Fetched::Klines {
+                    symbol,
+                    interval,
+                    result,
+                } => {
+                    match result {
+                        Ok(klines) => {
+                            println!(
+                                "VHAKM: Backfilled {} {} {} klines",
+                                klines.len(),
+                                symbol,
+                                interval
+                            );
+                            self.record(Entry::Backfill(klines.clone()));
+                            self.backfill(&klines);
+                        }
+                        Err(e) => {
+                            println!("VHAKM: Backfill of {} {} failed: {}", symbol, interval, e)
+                        }
+                    }
+
+                    for event in self.held.remove(&(symbol, interval)).unwrap_or_default() {
+                        intents.extend(self.kline(event));
+                    }
+                }
+                Fetched::Depth { symbol, result } => match result {
+                    Ok(snapshot) => self.snapshot(symbol, snapshot),
+                    Err(e) => println!("VHAKM: {} order book snapshot failed: {}", symbol, e),
+                },
+            }
+        }
+
+        intents
+    }
+
+    pub fn snapshot(&mut self, symbol: String, snapshot: model::OrderBook) {
+        if self.recorder.is_some() {
+            self.record(Entry::Snapshot {
+                symbol: symbol.to_string(),
+                book: snapshot.clone(),
+            });
+        }
+
+        self.books
+            .entry(symbol.to_string())
+            .or_insert_with(|| OrderBook::new(&symbol))
+            .snapshot(snapshot);
+    }
+
+    fn depth(&mut self, update: DepthOrderBookEvent) {
+        let symbol = update.symbol.to_string();
+        let time = update.event_time as i64;
+
+        let book = self.books.entry(symbol.to_string()).or_insert_with(|| OrderBook::new(&symbol));
+
+        // the updates wait in the book until the snapshot comes back from the worker.
+        if let (true, Some(fetcher)) = (book.wants_snapshot(time), &self.fetcher) {
+            book.requested = Some(time);
+            fetcher.request(Request::Depth {
+                symbol: symbol.to_string(),
+            });
+        }
+
+        if self.recorder.is_some() {
+            self.record(Entry::Event(WebsocketEvent::DepthOrderBook(update.clone())));
+        }
+
+        let Some(book) = self.books.get_mut(&symbol) else {
+            return;
+        };
+
+        if book.update(update) == Sync::Gap {
+            println!("VHAKM: {} order book missed updates, resyncing", symbol);
+        }
+
+        let Some(config) = &self.fleet.config.depth else {
+            return;
+        };
+
+        if !book.due(time, config.publish_ms) {
+            return;
+        }
+
+        let metrics = book.metrics(&config.bands, time);
+
+        if let (Some(metrics), Some(hooks)) = (metrics, self.hooks.get(&symbol)) {
+            hooks.depth(&metrics, config.retention_ms());
+        }
+    }
+
+    fn trade(&mut self, event: AggrTradesEvent) {
+        if self.recorder.is_some() {
+            self.record(Entry::Event(WebsocketEvent::AggrTrades(event.clone())));
+        }
+
+        let Some(config) = &self.fleet.config.order_flow else {
+            return;
+        };
+
+        let Some(trade) = Trade::from_event(&event) else {
+            println!("VHAKM: {} unreadable trade {:?}", event.symbol, event);
+            return;
+        };
+
+        let symbol = event.symbol;
+        let flow = self.flows.entry(symbol.to_string()).or_insert_with(|| TradeFlow::new(&symbol));
+
+        flow.push(trade, config);
+
+        if !flow.due(trade.time, config.publish_ms) {
+            return;
+        }
+
+        let flows = flow.flows(config, trade.time);
+
+        if let Some(hooks) = self.hooks.get(&symbol) {
+            hooks.order_flow(&flows, config.retention_ms());
+        }
+    }
+
+    fn kline(&mut self, kline_event: KlineEvent) -> Vec<(String, Intent)> {
+        let kline = Kline::from(&kline_event);
+        let time = kline_event.event_time as i64;
+
+        let stream = (kline.symbol.to_string(), kline.interval.to_string());
+
+        // the gap is stored before the bots see the first kline after it, the stream waits for
+        // its backfill meanwhile.
+        if let Some(held) = self.held.get_mut(&stream) {
+            held.push(kline_event);
+            return Vec::new();
+        }
+
+        if let (Some(gap), Some(fetcher)) = (self.gaps.check(&kline), &self.fetcher) {
+            fetcher.request(Request::Klines {
+                symbol: kline.symbol.to_string(),
+                interval: kline.interval.to_string(),
+                gap,
+            });
+            self.held.insert(stream, vec![kline_event]);
+            return Vec::new();
+        }
+
+        self.record(Entry::Event(WebsocketEvent::Kline(kline_event)));
+
+        let mut intents = if kline.interval == strategy::TICK_INTERVAL {
+            strategy::dispatch(self.fleet.strategies_mut(), &kline, time)
+        } else {
+            strategy::dispatch_candle(self.fleet.strategies_mut(), &kline, time)
+        };
+
+        let aggregated = self.candles.push(&kline);
+
+        for candle in &aggregated {
+            intents.extend(strategy::dispatch_candle(self.fleet.strategies_mut(), candle, time));
+        }
+
+        if let Some(hooks) = self.hooks.get_mut(&kline.symbol) {
+            let retention_ms = self.fleet.config.variations.retention_ms();
+
+            for kline in std::iter::once(&kline).chain(&aggregated) {
+                hooks.kline(kline, retention_ms);
+            }
+        }
+
+        strategy::report(&intents);
+
+        intents
+    }
+}
@@ -0,0 +1,356 @@
+// Signal-driven bot: a StrategyConfig is evaluated on every closed kline of the configured
+// interval, BUY/SELL signals open (or flip) the position and the SimpleTrading underneath manages
+// it with the same TP/SL, exits, costs and ledger as the movement-watch bots.
+
+use crate::ledger::{ExitReason, Side};
+use crate::order_flow::OrderFlow;
+use crate::simple_trading::{SimpleTrading, REDIS_URL};
+use crate::strategy::{interval_ms, Intent, Kline, Strategy};
+use crate::utils::strategy_builder::strategy_builder::{
+    load_strategy_config_from_file, IndicatorData, Signal, StrategyBuilder, StrategyConfig,
+};
+use redis::Commands;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub fn default_interval() -> String {
+    "1s".to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignalConfig {
+    // Path of the StrategyConfig JSON, see src/bots/weighted.json.
+    pub strategy: String,
+    // Redis sorted set holding the indicator data the strategy reads.
+    pub key: String,
+    // Interval of the klines evaluated, only their final update counts.
+    #[serde(default = "default_interval")]
+    pub interval: String,
+    // Indicator data older than this at the close of the kline holds the signal, two intervals
+    // when absent.
+    #[serde(default)]
+    pub max_age_ms: Option<i64>,
+}
+
+impl SignalConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.key.is_empty() {
+            return Err("key must not be empty".to_string());
+        }
+
+        if let Some(max_age_ms) = self.max_age_ms.filter(|max_age_ms| *max_age_ms <= 0) {
+            return Err(format!("max_age_ms must be positive, got {}", max_age_ms));
+        }
+
+        load_strategy_config_from_file(&self.strategy)
+            .map(|_| ())
+            .map_err(|e| format!("strategy {}: {}", self.strategy, e))
+    }
+
+    pub fn source(&self) -> Box<dyn SignalSource> {
+        let config = load_strategy_config_from_file(&self.strategy).unwrap_or_else(|e| {
+            println!("VHAKM: Could not load strategy {}: {}", self.strategy, e);
+
+            StrategyConfig {
+                indicators: HashMap::new(),
+            }
+        });
+
+        Box::new(RedisSignals::new(config, &self.key, self.max_age_ms()))
+    }
+
+    pub fn max_age_ms(&self) -> i64 {
+        self.max_age_ms
+            .unwrap_or_else(|| 2 * interval_ms(&self.interval).unwrap_or(1_000))
+    }
+}
+
+pub trait SignalSource {
+    fn signal(&mut self, kline: &Kline) -> Result<Signal, Box<dyn std::error::Error>>;
+}
+
+// StrategyBuilder reading the indicator history of `key` from Redis. eddie does not compute
+// these indicators: the TypeScript indicator process adds an IndicatorData JSON per update to the
+// sorted set, and a signal is only evaluated while its newest point is fresh at the kline close.
+// The order flow windows published by eddie (see order_flow) follow the same rule.
+pub struct RedisSignals {
+    builder: StrategyBuilder,
+    redis: redis::Client,
+    key: String,
+    order_flow: Option<String>,
+    max_age_ms: i64,
+    // Set while the indicators are too old, reported once.
+    stale: bool,
+}
+
+impl RedisSignals {
+    pub fn new(config: StrategyConfig, key: &str, max_age_ms: i64) -> Self {
+        Self {
+            order_flow: config.order_flow_key().map(str::to_string),
+            builder: StrategyBuilder::new(REDIS_URL, config).unwrap(),
+            redis: redis::Client::open(REDIS_URL).unwrap(),
+            key: key.to_string(),
+            max_age_ms,
+            stale: false,
+        }
+    }
+
+    // Whether the newest points of the keys, `age` ms before the kline close, can be traded on.
+    fn fresh(&mut self, ages: &[(String, i64)]) -> bool {
+        let Some((key, age)) = ages.iter().find(|(_, age)| *age > self.max_age_ms) else {
+            self.stale = false;
+            return true;
+        };
+
+        if !self.stale {
+            println!("{}, Indicators {} ms old at the kline close, holding", key, age);
+        }
+
+        self.stale = true;
+        false
+    }
+}
+
+impl SignalSource for RedisSignals {
+    fn signal(&mut self, kline: &Kline) -> Result<Signal, Box<dyn std::error::Error>> {
+        let mut con = self.redis.get_connection()?;
+        let latest: Vec<String> = con.zrevrange(&self.key, 0, 0)?;
+
+        // the builder expects some history, hold until the indicators are there.
+        let Some(latest) = latest.first() else {
+            return Ok(Signal::HOLD);
+        };
+
+        let latest: IndicatorData = serde_json::from_str(latest)?;
+        let mut ages = vec![(self.key.to_string(), kline.close_time - latest.time_ms())];
+
+        // no window yet adds nothing, a stalled trade stream holds like the indicators.
+        if let Some(key) = &self.order_flow {
+            let window: Vec<String> = con.zrevrange(key, 0, 0)?;
+
+            if let Some(window) = window.first() {
+                let window: OrderFlow = serde_json::from_str(window)?;
+                ages.push((key.to_string(), kline.close_time - window.time));
+            }
+        }
+
+        if !self.fresh(&ages) {
+            return Ok(Signal::HOLD);
+        }
+
+        self.builder.evaluate(&self.key)
+    }
+}
+
+pub struct SignalTrading {
+    pub trader: SimpleTrading,
+    pub interval: String,
+    pub last_signal: Option<Signal>,
+    source: Box<dyn SignalSource>,
+}
+
+impl SignalTrading {
+    pub fn new(mut trader: SimpleTrading, interval: &str, source: Box<dyn SignalSource>) -> Self {
+        trader.movement_entries = false;
+
+        Self {
+            trader,
+            interval: interval.to_string(),
+            last_signal: None,
+            source,
+        }
+    }
+
+    pub fn set_source(&mut self, interval: &str, source: Box<dyn SignalSource>) {
+        self.interval = interval.to_string();
+        self.source = source;
+    }
+
+    // Go `side` at `price`, closing the opposite position first.
+    fn follow(&mut self, side: Side, price: f64, time: i64) -> Vec<Intent> {
+        let mut intents = Vec::new();
+
+        let opposite = match side {
+            Side::Long => self.trader.sell_price != 0.0,
+            Side::Short => self.trader.buy_price != 0.0,
+        };
+
+        if self.trader.trade_active && opposite {
+            intents.extend(
+                self.trader
+                    .close_at(price, time, ExitReason::Signal)
+                    .map(Intent::Close),
+            );
+        }
+
+        intents.extend(self.trader.enter(side, price, time));
+
+        intents
+    }
+}
+
+impl Strategy for SignalTrading {
+    fn name(&self) -> &str {
+        &self.trader.name
+    }
+
+    fn family(&self) -> &str {
+        &self.trader.family
+    }
+
+    fn symbol(&self) -> &str {
+        &self.trader.symbol
+    }
+
+    fn on_tick(&mut self, price: f64, time: i64) -> Vec<Intent> {
+        self.trader.on_tick(price, time)
+    }
+
+    fn on_kline(&mut self, kline: &Kline, time: i64) -> Vec<Intent> {
+        if !kline.closed || kline.interval != self.interval {
+            return Vec::new();
+        }
+
+        let signal = match self.source.signal(kline) {
+            Ok(signal) => signal,
+            Err(e) => {
+                println!("{}, Could not evaluate signal: {}", self.trader.name, e);
+                return Vec::new();
+            }
+        };
+
+        self.last_signal = Some(signal);
+
+        match signal {
+            Signal::BUY => self.follow(Side::Long, kline.close, time),
+            Signal::SELL => self.follow(Side::Short, kline.close, time),
+            Signal::HOLD => Vec::new(),
+        }
+    }
+
+    fn state(&self) -> serde_json::Value {
+        self.trader.state()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Replays a fixed list of signals, HOLD once exhausted.
+    struct ScriptedSignals(Vec<Signal>);
+
+    impl SignalSource for ScriptedSignals {
+        fn signal(&mut self, kline: &Kline) -> Result<Signal, Box<dyn std::error::Error>> {
+            Ok(if self.0.is_empty() { Signal::HOLD } else { self.0.remove(0) })
+        }
+    }
+
+    fn kline(close: f64, closed: bool) -> Kline {
+        Kline {
+            symbol: "BTCUSDT".to_string(),
+            interval: "1m".to_string(),
+            open_time: 0,
+            close_time: 59_999,
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: 1.0,
+            closed,
+        }
+    }
+
+    fn signal_bot(signals: Vec<Signal>) -> SignalTrading {
+        let trader = SimpleTrading::new("SIGNAL".to_string(), 0.0, 0.03 / 100.0, 5.0, 4.0);
+
+        SignalTrading::new(trader, "1m", Box::new(ScriptedSignals(signals)))
+    }
+
+    // (price, closed kline) updates, the close price is also the tick like in the websocket loop.
+    fn run(bot: &mut SignalTrading, updates: &[(f64, bool)]) -> Vec<Intent> {
+        let mut intents = Vec::new();
+
+        for (time, (price, closed)) in updates.iter().enumerate() {
+            intents.extend(bot.on_kline(&kline(*price, *closed), time as i64 * 1_000));
+            intents.extend(bot.on_tick(*price, time as i64 * 1_000));
+        }
+
+        intents
+    }
+
+    #[test]
+    fn test_signal_entry_and_take_profit() {
+        let mut bot = signal_bot(vec![Signal::HOLD, Signal::BUY]);
+
+        // a 0.05% move would trigger a movement-watch bot, not a signal bot.
+        let intents = run(&mut bot, &[(100_000.0, true), (100_050.0, false), (100_100.0, false)]);
+        assert!(intents.is_empty());
+
+        let intents = run(&mut bot, &[(100_000.0, true), (100_010.0, false), (100_040.0, false)]);
+        assert_eq!(
+            intents[0],
+            Intent::OpenLong {
+                price: 100_000.0,
+                quantity: 1.0
+            }
+        );
+
+        let trade = intents[1].clone().trade().unwrap();
+        assert_eq!(trade.exit_reason, ExitReason::TakeProfit);
+        assert_eq!(trade.gross_pnl, 40.0);
+        assert!(!bot.trader.trade_active);
+    }
+
+    #[test]
+    fn test_opposite_signal_flips() {
+        let mut bot = signal_bot(vec![Signal::SELL, Signal::SELL, Signal::BUY]);
+
+        let intents = run(&mut bot, &[(100_000.0, true), (99_998.0, true), (99_997.0, true)]);
+
+        assert_eq!(intents.len(), 3);
+        assert!(matches!(intents[0], Intent::OpenShort { price, .. } if price == 100_000.0));
+
+        let trade = intents[1].clone().trade().unwrap();
+        assert_eq!(trade.exit_reason, ExitReason::Signal);
+        assert_eq!(trade.gross_pnl, 3.0);
+
+        assert!(matches!(intents[2], Intent::OpenLong { price, .. } if price == 99_997.0));
+        assert_eq!(bot.trader.buy_price, 99_997.0);
+        assert_eq!(bot.last_signal, Some(Signal::BUY));
+    }
+
+    #[test]
+    fn test_indicator_freshness() {
+        let mut config = SignalConfig {
+            strategy: "src/bots/weighted.json".to_string(),
+            key: "indicators:BTCUSDT:1m".to_string(),
+            interval: "1m".to_string(),
+            max_age_ms: None,
+        };
+
+        assert_eq!(config.max_age_ms(), 120_000);
+
+        config.max_age_ms = Some(0);
+        assert!(config.validate().is_err());
+
+        let indicators = StrategyConfig {
+            indicators: HashMap::new(),
+        };
+        let mut signals = RedisSignals::new(indicators, "indicators:BTCUSDT:1m", 120_000);
+        assert_eq!(signals.order_flow, None);
+
+        let age = |key: &str, age: i64| (key.to_string(), age);
+        let flow = "variations:order_flow:BTCUSDT:1m";
+
+        assert!(signals.fresh(&[age("indicators:BTCUSDT:1m", 120_000)]));
+        assert!(!signals.fresh(&[age("indicators:BTCUSDT:1m", 120_001)]));
+        assert!(signals.stale);
+        assert!(signals.fresh(&[age("indicators:BTCUSDT:1m", -500), age(flow, 1_000)]));
+        assert!(!signals.stale);
+
+        // a stalled trade stream holds even with fresh indicators.
+        assert!(!signals.fresh(&[age("indicators:BTCUSDT:1m", 500), age(flow, 300_000)]));
+        assert!(signals.stale);
+    }
+}
@@ -1,9 +1,88 @@
+use crate::account::{self, Account, EquityPoint};
+use crate::costs::TradingCosts;
+use crate::exits::{AverageTrueRange, ExitRules};
+use crate::ledger::{self, ExitReason, Side, TradeRecord};
+use crate::limits::{self, TradeLimits};
+use crate::sizing::PositionSizing;
+use crate::strategy::{Intent, Strategy};
+use crate::symbols::SymbolRules;
 use chrono::Utc;
 use redis::Commands;
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell, RefMut};
+use std::time::{Duration, Instant};
+
+// Redis hash holding the JSON snapshot of every bot, keyed by bot name.
+pub const STATE_KEY: &str = "trades:state";
+
+pub const REDIS_URL: &str = "redis://127.0.01:6179";
+
+// Redis is tried again this long after a failed connection, not on every tick.
+pub const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SimpleTradingState {
+    pub last_price: f64,
+    pub watch_price: f64,
+    pub buy_price: f64,
+    pub sell_price: f64,
+    pub stop_loss: f64,
+    pub take_profit: f64,
+    pub profits: f64,
+    pub losses: f64,
+    #[serde(default)]
+    pub costs_paid: f64,
+    pub trade_active: bool,
+    // snapshots written before sizing existed traded one unit.
+    #[serde(default = "default_quantity")]
+    pub quantity: f64,
+    #[serde(default)]
+    pub entry_time: i64,
+    #[serde(default)]
+    pub max_adverse_excursion: f64,
+    #[serde(default)]
+    pub max_favourable_excursion: f64,
+    #[serde(default = "default_stop_reason")]
+    pub stop_reason: ExitReason,
+    #[serde(default)]
+    pub best_price: f64,
+    #[serde(default)]
+    pub initial_quantity: f64,
+    #[serde(default)]
+    pub partials_taken: usize,
+    #[serde(default)]
+    pub cooldown_until: i64,
+    #[serde(default)]
+    pub trading_day: i64,
+    #[serde(default)]
+    pub trades_today: u32,
+    // account, missing in snapshots written before bots had a balance.
+    #[serde(default)]
+    pub balance: Option<f64>,
+    #[serde(default)]
+    pub peak_equity: f64,
+    #[serde(default)]
+    pub max_drawdown: f64,
+    // missing in snapshots written before it was kept, the entry time is used then.
+    #[serde(default)]
+    pub last_time: i64,
+}
+
+fn default_quantity() -> f64 {
+    1.0
+}
+
+fn default_stop_reason() -> ExitReason {
+    ExitReason::StopLoss
+}
 
 pub struct SimpleTrading {
+    pub name: String,
+    pub family: String,
     pub symbol: String,
     pub last_price: f64,
+    // Event time (ms) of the last tick, a bot removed from the fleet is closed at it.
+    pub last_time: i64,
     pub watch_price: f64,
     pub buy_price: f64,
     pub sell_price: f64,
@@ -11,16 +90,51 @@ pub struct SimpleTrading {
     pub take_profit: f64,
     pub profits: f64,
     pub losses: f64,
+    // Fees, slippage and funding of every closed trade, profits and losses are gross.
+    pub costs_paid: f64,
     pub trade_active: bool,
+    // Base asset quantity of the open position.
+    pub quantity: f64,
+    pub initial_quantity: f64,
+    pub entry_time: i64,
+    // Worst and best price move seen while the trade is open, per unit of the base asset.
+    pub max_adverse_excursion: f64,
+    pub max_favourable_excursion: f64,
+    // Best price seen since the entry, the trailing stop follows it.
+    pub best_price: f64,
+    // Reported when the stop is hit: a plain, break-even or trailing stop.
+    pub stop_reason: ExitReason,
+    pub partials_taken: usize,
+    // Event time (ms) until which no new trade is entered, set by a stop loss.
+    pub cooldown_until: i64,
+    // UTC day of the last entry and the trades opened on it.
+    pub trading_day: i64,
+    pub trades_today: u32,
     pub redis: redis::Client,
+    // Opened on the first write and kept, dropped when redis closes it.
+    connection: RefCell<Option<redis::Connection>>,
+    // Set while redis is unreachable, the outage is reported once.
+    reconnect_at: Cell<Option<Instant>>,
     pub take_profit_percentage: f64,
     pub stop_loss_value_usd: f64,
     pub watch_movement_percentage: f64,
+    pub costs: TradingCosts,
+    pub sizing: PositionSizing,
+    pub leverage: f64,
+    pub account: Account,
+    pub exits: ExitRules,
+    pub atr: AverageTrueRange,
+    pub limits: TradeLimits,
+    pub rules: SymbolRules,
+    // Off for bots whose entries come from elsewhere, e.g. a SignalTrading.
+    pub movement_entries: bool,
+    // Off in backtests: no state, ledger, equity or totals are written to redis.
+    pub persist: bool,
 }
 
 impl SimpleTrading {
     pub fn new(
-        symbol: String,
+        name: String,
         price: f64,
         take_profit_percentage: f64,
         stop_loss_value_usd: f64,
@@ -30,20 +144,52 @@ impl SimpleTrading {
             take_profit_percentage,
             stop_loss_value_usd,
             watch_movement_percentage,
-            redis: redis::Client::open("redis://127.0.01:6179").unwrap(),
-            symbol,
+            redis: redis::Client::open(REDIS_URL).unwrap(),
+            connection: RefCell::new(None),
+            reconnect_at: Cell::new(None),
+            name,
+            family: String::new(),
+            symbol: String::new(),
             watch_price: price,
             last_price: 0.0,
+            last_time: 0,
             buy_price: 0.0,
             sell_price: 0.0,
             stop_loss: 0.0,
             take_profit: 0.0,
             profits: 0.0,
             losses: 0.0,
+            costs_paid: 0.0,
             trade_active: false,
+            quantity: 0.0,
+            initial_quantity: 0.0,
+            entry_time: 0,
+            max_adverse_excursion: 0.0,
+            max_favourable_excursion: 0.0,
+            best_price: 0.0,
+            stop_reason: ExitReason::StopLoss,
+            partials_taken: 0,
+            cooldown_until: 0,
+            trading_day: 0,
+            trades_today: 0,
+            costs: TradingCosts::default(),
+            sizing: PositionSizing::default(),
+            leverage: 1.0,
+            account: Account::new(0.0, false),
+            exits: ExitRules::default(),
+            atr: AverageTrueRange::new(crate::exits::DEFAULT_ATR_PERIOD),
+            limits: TradeLimits::default(),
+            rules: SymbolRules::default(),
+            movement_entries: true,
+            // unit tests never write to the redis of the live bots.
+            persist: !cfg!(test),
         }
     }
 
+    pub fn net_result(&self) -> f64 {
+        self.profits - self.losses - self.costs_paid
+    }
+
     pub fn movement_percentage(&self) -> f64 {
         let price_diff = self.last_price - self.watch_price;
         (price_diff / self.watch_price) * 100.0
@@ -57,16 +203,463 @@ impl SimpleTrading {
         self.stop_loss = 0.0;
         self.take_profit = 0.0;
         self.trade_active = false;
+        self.quantity = 0.0;
+        self.initial_quantity = 0.0;
+        self.entry_time = 0;
+        self.max_adverse_excursion = 0.0;
+        self.max_favourable_excursion = 0.0;
+        self.best_price = 0.0;
+        self.stop_reason = ExitReason::StopLoss;
+        self.partials_taken = 0;
+    }
+
+    // Close the whole open position at `price`, outside of the TP/SL checks of a tick.
+    pub fn close_at(&mut self, price: f64, time: i64, exit_reason: ExitReason) -> Option<TradeRecord> {
+        if !self.trade_active {
+            return None;
+        }
+
+        let trade = self.exit(price, time, exit_reason, self.quantity);
+
+        self.report();
+        self.save_state();
+
+        Some(trade)
+    }
+
+    // Open a position at `price` without waiting for a watch movement, the following ticks
+    // manage it like any other. Nothing happens while a trade is open or entries are blocked.
+    pub fn enter(&mut self, side: Side, price: f64, time: i64) -> Option<Intent> {
+        if self.trade_active || self.entry_blocked(time) {
+            return None;
+        }
+
+        let quantity = self.position_quantity(price);
+
+        if !self.rules.tradable(quantity) {
+            return None;
+        }
+
+        self.quantity = quantity;
+
+        let [current_price, stop_loss, take_profit] = match side {
+            Side::Long => {
+                self.buy_price = price;
+                self.buy(price)
+            }
+            Side::Short => {
+                self.sell_price = price;
+                self.sell(price)
+            }
+        };
+
+        self.open(price, time);
+        self.stop_loss = stop_loss;
+        self.take_profit = take_profit;
+        self.last_price = price;
+        self.last_time = time;
+        self.save_state();
+
+        Some(Intent::open(side, price, self.quantity))
+    }
+
+    fn open(&mut self, price: f64, time: i64) {
+        self.trade_active = true;
+        self.entry_time = time;
+        self.initial_quantity = self.quantity;
+        self.max_adverse_excursion = 0.0;
+        self.max_favourable_excursion = 0.0;
+        self.best_price = price;
+        self.stop_reason = ExitReason::StopLoss;
+        self.partials_taken = 0;
+
+        let day = limits::trading_day(time);
+
+        if day != self.trading_day {
+            self.trading_day = day;
+            self.trades_today = 0;
+        }
+
+        self.trades_today += 1;
+    }
+
+    // True while the bot waits after a stop loss or has used its trades for the day.
+    pub fn entry_blocked(&self, time: i64) -> bool {
+        time < self.cooldown_until
+            || (limits::trading_day(time) == self.trading_day
+                && self.limits.daily_cap_reached(self.trades_today))
+    }
+
+    fn is_long(&self) -> bool {
+        self.buy_price != 0.0
+    }
+
+    fn entry_price(&self) -> f64 {
+        if self.is_long() {
+            self.buy_price
+        } else {
+            self.sell_price
+        }
+    }
+
+    // Favourable price move from the entry, negative when the trade is losing.
+    fn favourable_move(&self, price: f64) -> f64 {
+        if self.is_long() {
+            price - self.buy_price
+        } else {
+            self.sell_price - price
+        }
+    }
+
+    // Move the stop to break-even and trail it behind the best price, returns true when it moved.
+    fn manage_stop(&mut self, price: f64) -> bool {
+        if self.favourable_move(price) > self.favourable_move(self.best_price) {
+            self.best_price = price;
+        }
+
+        let entry_price = self.entry_price();
+        let direction = if self.is_long() { 1.0 } else { -1.0 };
+        let mut moved = false;
+
+        if let Some(break_even) = self.exits.break_even {
+            let trigger = entry_price * break_even.trigger_percentage / 100.0;
+
+            if self.favourable_move(self.best_price) >= trigger {
+                let stop = entry_price + direction * entry_price * break_even.offset_percentage / 100.0;
+                moved |= self.tighten_stop(stop, ExitReason::BreakEven);
+            }
+        }
+
+        if let Some(trailing_stop) = self.exits.trailing_stop {
+            if let Some(distance) = trailing_stop.distance(self.best_price, self.quantity, &self.atr) {
+                let stop = self.best_price - direction * distance;
+                moved |= self.tighten_stop(stop, ExitReason::TrailingStop);
+            }
+        }
+
+        moved
+    }
+
+    fn tighten_stop(&mut self, stop: f64, reason: ExitReason) -> bool {
+        let stop = self.rules.round_price(stop);
+
+        let tighter = if self.is_long() {
+            stop > self.stop_loss
+        } else {
+            stop < self.stop_loss
+        };
+
+        if tighter {
+            self.stop_loss = stop;
+            self.stop_reason = reason;
+        }
+
+        tighter
     }
 
-    pub fn run(&mut self, price: f64) {
+    // Close the configured fractions of the initial quantity at their levels.
+    fn take_partial_profits(&mut self, price: f64, time: i64) -> Vec<TradeRecord> {
+        let mut trades = Vec::new();
+        let partials = self.exits.sorted_partials();
+        let entry_price = self.entry_price();
+
+        while let Some(partial) = partials.get(self.partials_taken) {
+            if self.favourable_move(price) < entry_price * partial.percentage / 100.0 {
+                break;
+            }
+
+            let quantity = self
+                .rules
+                .round_quantity(self.initial_quantity * partial.fraction)
+                .min(self.quantity);
+
+            self.partials_taken += 1;
+
+            // a fraction smaller than the lot size stays in the position.
+            if quantity > 0.0 {
+                trades.push(self.exit(price, time, ExitReason::PartialTakeProfit, quantity));
+            }
+        }
+
+        trades
+    }
+
+    fn position_quantity(&self, price: f64) -> f64 {
+        self.rules.round_quantity(
+            self.sizing
+                .quantity(price, self.account.sizing_balance(), self.leverage),
+        )
+    }
+
+    // Gross result of the open position at `price`.
+    pub fn unrealized_pnl(&self, price: f64) -> f64 {
+        if !self.trade_active {
+            0.0
+        } else if self.buy_price != 0.0 {
+            (price - self.buy_price) * self.quantity
+        } else {
+            (self.sell_price - price) * self.quantity
+        }
+    }
+
+    // `result` is the favourable price move of the open position at the current price.
+    fn track_excursion(&mut self, result: f64) {
+        self.max_favourable_excursion = self.max_favourable_excursion.max(result);
+        self.max_adverse_excursion = self.max_adverse_excursion.max(-result);
+    }
+
+    // Close `quantity` of the open position, the trade is over once nothing is left.
+    fn exit(&mut self, price: f64, time: i64, exit_reason: ExitReason, quantity: f64) -> TradeRecord {
+        let (side, entry_price, price_diff) = if self.buy_price != 0.0 {
+            (Side::Long, self.buy_price, price - self.buy_price)
+        } else {
+            (Side::Short, self.sell_price, self.sell_price - price)
+        };
+
+        let mut result = price_diff * quantity;
+
+        // a stop loss books at most `stop_loss_value_usd`, whatever the price it was hit at.
+        if exit_reason == ExitReason::StopLoss {
+            result = result.max(-self.stop_loss_value_usd);
+        }
+
+        self.track_excursion(price_diff);
+
+        if result >= 0.0 {
+            self.profits += result;
+        } else {
+            self.losses -= result;
+        }
+
+        let costs = self.costs.round_trip(
+            side,
+            entry_price,
+            price,
+            quantity,
+            exit_reason,
+            time - self.entry_time,
+        );
+
+        self.costs_paid += costs.total();
+
+        let trade = TradeRecord {
+            bot: self.name.to_string(),
+            family: self.family.to_string(),
+            symbol: self.symbol.to_string(),
+            side,
+            quantity,
+            leverage: self.leverage,
+            entry_price,
+            entry_time: self.entry_time,
+            exit_price: price,
+            exit_time: time,
+            exit_reason,
+            gross_pnl: result,
+            fees: costs.fees,
+            slippage: costs.slippage,
+            funding: costs.funding,
+            net_pnl: result - costs.total(),
+            max_adverse_excursion: self.max_adverse_excursion * quantity,
+            max_favourable_excursion: self.max_favourable_excursion * quantity,
+        };
+
+        self.account.realize(trade.net_pnl);
+        let point = self.account.point(time);
+
+        if let Some(mut con) = self.connection() {
+            if let Err(e) = ledger::record(&mut con, &trade) {
+                println!("{}, Could not record trade: {}", self.name, e);
+            }
+
+            self.save_equity(&mut con, &point);
+        }
+
+        self.quantity -= quantity;
+
+        if self.quantity <= self.initial_quantity * 1e-9 {
+            if exit_reason == ExitReason::StopLoss {
+                self.cooldown_until = time + self.limits.cooldown_ms();
+            }
+
+            self.reset();
+        }
+
+        trade
+    }
+
+    fn exit_all(&mut self, price: f64, time: i64, exit_reason: ExitReason) -> Intent {
+        Intent::Close(self.exit(price, time, exit_reason, self.quantity))
+    }
+
+    // Drop the open position without booking anything, nothing is written to the ledger.
+    pub fn abandon(&mut self) {
+        self.reset();
+        self.save_state();
+    }
+
+    pub fn snapshot(&self) -> SimpleTradingState {
+        SimpleTradingState {
+            last_price: self.last_price,
+            watch_price: self.watch_price,
+            buy_price: self.buy_price,
+            sell_price: self.sell_price,
+            stop_loss: self.stop_loss,
+            take_profit: self.take_profit,
+            profits: self.profits,
+            losses: self.losses,
+            costs_paid: self.costs_paid,
+            trade_active: self.trade_active,
+            quantity: self.quantity,
+            entry_time: self.entry_time,
+            max_adverse_excursion: self.max_adverse_excursion,
+            max_favourable_excursion: self.max_favourable_excursion,
+            stop_reason: self.stop_reason,
+            best_price: self.best_price,
+            initial_quantity: self.initial_quantity,
+            partials_taken: self.partials_taken,
+            cooldown_until: self.cooldown_until,
+            trading_day: self.trading_day,
+            trades_today: self.trades_today,
+            balance: Some(self.account.balance),
+            peak_equity: self.account.peak_equity,
+            max_drawdown: self.account.max_drawdown,
+            last_time: self.last_time,
+        }
+    }
+
+    pub fn restore(&mut self, state: SimpleTradingState) {
+        self.last_price = state.last_price;
+        self.last_time = state.last_time.max(state.entry_time);
+        self.watch_price = state.watch_price;
+        self.buy_price = state.buy_price;
+        self.sell_price = state.sell_price;
+        self.stop_loss = state.stop_loss;
+        self.take_profit = state.take_profit;
+        self.profits = state.profits;
+        self.losses = state.losses;
+        self.costs_paid = state.costs_paid;
+        self.trade_active = state.trade_active;
+        self.quantity = state.quantity;
+        self.entry_time = state.entry_time;
+        self.max_adverse_excursion = state.max_adverse_excursion;
+        self.max_favourable_excursion = state.max_favourable_excursion;
+        self.stop_reason = state.stop_reason;
+        self.best_price = state.best_price;
+        self.partials_taken = state.partials_taken;
+        self.cooldown_until = state.cooldown_until;
+        self.trading_day = state.trading_day;
+        self.trades_today = state.trades_today;
+        self.initial_quantity = if state.initial_quantity > 0.0 {
+            state.initial_quantity
+        } else {
+            state.quantity
+        };
+
+        if self.trade_active && self.best_price == 0.0 {
+            self.best_price = self.entry_price();
+        }
+
+        if let Some(balance) = state.balance {
+            self.account.balance = balance;
+            self.account.peak_equity = state.peak_equity;
+            self.account.max_drawdown = state.max_drawdown;
+        }
+    }
+
+    // Reload the snapshot saved by a previous process, if any.
+    pub fn load_state(&mut self) -> bool {
+        let Some(mut con) = self.connection() else {
+            return false;
+        };
+
+        let state: Option<String> = match con.hget(STATE_KEY, &self.name) {
+            Ok(state) => state,
+            Err(e) => {
+                println!("{}, Could not read state: {}", self.name, e);
+                return false;
+            }
+        };
+
+        drop(con);
+
+        match state.map(|json| serde_json::from_str::<SimpleTradingState>(&json)) {
+            Some(Ok(state)) => {
+                self.restore(state);
+                true
+            }
+            Some(Err(e)) => {
+                println!("{}, Ignoring invalid state: {}", self.name, e);
+                false
+            }
+            None => false,
+        }
+    }
+
+    fn save_equity(&self, con: &mut redis::Connection, point: &EquityPoint) {
+        let value = serde_json::to_string(point).unwrap();
+
+        let _: redis::RedisResult<()> = con.zadd(account::equity_key(&self.name), value, point.time);
+    }
+
+    fn save_state(&self) {
+        let Some(mut con) = self.connection() else {
+            return;
+        };
+
+        let state = serde_json::to_string(&self.snapshot()).unwrap();
+
+        let _: redis::RedisResult<()> = con.hset(STATE_KEY, &self.name, state);
+    }
+
+    // Drop the saved snapshot, used when the bot leaves the fleet.
+    pub fn forget_state(&self) {
+        if let Some(mut con) = self.connection() {
+            let _: redis::RedisResult<()> = con.hdel(STATE_KEY, &self.name);
+        }
+    }
+
+    pub fn run(&mut self, price: f64) -> Vec<TradeRecord> {
+        self.run_at(price, Utc::now().timestamp_millis())
+    }
+
+    // `time` is the event time in milliseconds, returns the trades (or parts) closed on this tick.
+    pub fn run_at(&mut self, price: f64, time: i64) -> Vec<TradeRecord> {
+        self.tick(price, time)
+            .into_iter()
+            .filter_map(Intent::trade)
+            .collect()
+    }
+
+    fn tick(&mut self, price: f64, time: i64) -> Vec<Intent> {
         // println!("Current price: {}", current_price);
 
+        // every watch, open, stop move and close is saved so a restart resumes where we left off.
+        let mut state_changed = false;
+        let mut intents = Vec::new();
+
+        if self.last_price != 0.0 {
+            self.atr.update((price - self.last_price).abs());
+        }
+
+        let flat = self.buy_price == 0.0 && self.sell_price == 0.0;
+
         // check for price movement when there is no active trade.
-        if self.last_price != 0.0 && (self.buy_price == 0.0 && self.sell_price == 0.0) {
+        if flat && self.watch_price != 0.0 && self.entry_blocked(time) {
+            // the watch restarts from the current price once the bot may trade again.
+            self.watch_price = price;
+        } else if self.movement_entries && self.last_price != 0.0 && flat {
             let price_diff_percentage = self.movement_percentage();
 
-            if price_diff_percentage.abs() > (self.watch_movement_percentage / 100.0) {
+            let quantity = self.position_quantity(price);
+
+            if price_diff_percentage.abs() > (self.watch_movement_percentage / 100.0)
+                && !self.rules.tradable(quantity)
+            {
+                // below the minimum quantity of the symbol, keep watching from here.
+                self.watch_price = price;
+            } else if price_diff_percentage.abs() > (self.watch_movement_percentage / 100.0) {
+                self.quantity = quantity;
+
                 if price_diff_percentage > 0.0 {
                     let [p, s, t] = self.buy(price);
 
@@ -76,7 +669,7 @@ impl SimpleTrading {
 
                     // println!(
                     //     "{}, BUY TIME!! {}, {}%, SL: {}, TP: {}",
-                    //     self.symbol,
+                    //     self.name,
                     //     &price, price_diff_percentage, self.stop_loss, self.take_profit
                     // );
                 } else {
@@ -88,7 +681,7 @@ impl SimpleTrading {
 
                     // println!(
                     //     "{}, SELL TIME!! {}, {}%, SL: {}, TP: {}",
-                    //     self.symbol,
+                    //     self.name,
                     //     &price, price_diff_percentage, self.stop_loss, self.take_profit
                     // );
                 }
@@ -97,63 +690,79 @@ impl SimpleTrading {
 
         // check when there is a buy trade
         if self.buy_price != 0.0 {
-            if self.trade_active == false {
-                self.trade_active = true;
+            if !self.trade_active {
                 self.buy_price = price;
+                self.open(price, time);
 
                 let [current_price, stop_loss, take_profit] = self.buy(price);
                 self.stop_loss = stop_loss;
                 self.take_profit = take_profit;
+
+                state_changed = true;
+                intents.push(Intent::open(Side::Long, price, self.quantity));
             }
 
-            if price >= self.take_profit {
-                // println!("{}, Take profit hit: {}, {}", self.symbol, price, self.take_profit);
-                // println!("{}, We have made: {:.3}$", self.symbol, price - self.buy_price);
-                self.profits += price - self.buy_price;
+            self.track_excursion(price - self.buy_price);
+            state_changed |= self.manage_stop(price);
 
-                self.reset();
+            let partials = self.take_partial_profits(price, time);
+            state_changed |= !partials.is_empty();
+            intents.extend(partials.into_iter().map(Intent::Close));
+
+            if price >= self.take_profit {
+                // println!("{}, Take profit hit: {}, {}", self.name, price, self.take_profit);
+                // println!("{}, We have made: {:.3}$", self.name, price - self.buy_price);
+                intents.push(self.exit_all(price, time, ExitReason::TakeProfit));
             } else if price <= self.stop_loss {
-                // println!("{}, Stop loss hit: {}, {}", self.symbol, price, self.stop_loss);
+                // println!("{}, Stop loss hit: {}, {}", self.name, price, self.stop_loss);
                 // println!(
                 //     "{}, We have lost: {:.3}$",
-                //     self.symbol,
-                //     f64::max(price - self.buy_price, -self.stop_loss_value_usd)
+                //     self.name,
+                //     self.buy_price - price
                 // );
 
-                self.losses -= f64::max(price - self.buy_price, -self.stop_loss_value_usd);
-
-                self.reset();
+                intents.push(self.exit_all(price, time, self.stop_reason));
+            } else if self.limits.holding_expired(self.entry_time, time) {
+                intents.push(self.exit_all(price, time, ExitReason::MaxHoldingTime));
             }
         }
 
         // check when there is a buy trade
         if self.sell_price != 0.0 {
-            if self.trade_active == false {
-                self.trade_active = true;
+            if !self.trade_active {
                 self.sell_price = price;
+                self.open(price, time);
 
                 let [current_price, stop_loss, take_profit] = self.sell(price);
                 self.stop_loss = stop_loss;
                 self.take_profit = take_profit;
+
+                state_changed = true;
+                intents.push(Intent::open(Side::Short, price, self.quantity));
             }
 
-            if price <= self.take_profit {
-                // println!("{}, Take profit hit: {}, {}", self.symbol, price, self.take_profit);
-                // println!("{}, We have made: {:.3}$", self.symbol, self.sell_price - price);
-                self.profits += self.sell_price - price;
+            self.track_excursion(self.sell_price - price);
+            state_changed |= self.manage_stop(price);
 
-                self.reset();
+            let partials = self.take_partial_profits(price, time);
+            state_changed |= !partials.is_empty();
+            intents.extend(partials.into_iter().map(Intent::Close));
+
+            if price <= self.take_profit {
+                // println!("{}, Take profit hit: {}, {}", self.name, price, self.take_profit);
+                // println!("{}, We have made: {:.3}$", self.name, self.sell_price - price);
+                intents.push(self.exit_all(price, time, ExitReason::TakeProfit));
             } else if price >= self.stop_loss {
-                // println!("{}, Stop loss hit: {}, {}", self.symbol, price, self.stop_loss);
+                // println!("{}, Stop loss hit: {}, {}", self.name, price, self.stop_loss);
                 // println!(
                 //     "{}, We have lost: {:.3}$",
-                //     self.symbol,
-                //     f64::max(self.sell_price - price, -self.stop_loss_value_usd)
+                //     self.name,
+                //     price - self.sell_price
                 // );
 
-                self.losses -= f64::max(self.sell_price - price, -self.stop_loss_value_usd);
-
-                self.reset();
+                intents.push(self.exit_all(price, time, self.stop_reason));
+            } else if self.limits.holding_expired(self.entry_time, time) {
+                intents.push(self.exit_all(price, time, ExitReason::MaxHoldingTime));
             }
         }
 
@@ -164,61 +773,130 @@ impl SimpleTrading {
 
             // println!(
             //     "{}, Setting watch price: {}, BUY @ {:.3}, SELL @ {:.3}, DIFF in $ {:.1}",
-            //     self.symbol,
+            //     self.name,
             //     self.watch_price,
             //     self.watch_price + diff,
             //     self.watch_price - diff,
             //     diff,
             // );
 
-            let score = Utc::now().timestamp();
-
-            let _: () = self
-                .redis
-                .get_connection()
-                .unwrap()
-                .hset(
-                    "trades:debile".to_string(),
-                    self.symbol.to_string(),
-                    format!(
-                        "Total Profits: {:.3}, Total Losses {:.3}",
-                        self.profits, self.losses
-                    ),
-                )
-                .unwrap();
-
-            let _: () = self
-                .redis
-                .get_connection()
-                .unwrap()
-                .lpush(
-                    "trades:history".to_string(),
-                    format!(
-                        "S:{}, P:{:.1}, L{:.1}, T:{}",
-                        self.symbol.to_string(),
-                        self.profits, self.losses,
-                        score,
-                    )
-                )
-                .unwrap();
+            self.report();
+
+            state_changed = true;
 
             // println!(
             //     "{}, Total Profits: {:.3}, Total Losses {:.3}",
-            //     self.symbol,
+            //     self.name,
             //     self.profits, self.losses
             // );
         }
 
+        if let Some(point) = self.account.mark(time, self.unrealized_pnl(price)) {
+            if let Some(mut con) = self.connection() {
+                self.save_equity(&mut con, &point);
+            }
+        }
+
         self.last_price = price;
+        self.last_time = time;
+
+        if state_changed {
+            self.save_state();
+        }
+
+        intents
+    }
+
+    // The trading loop keeps going when redis is unreachable.
+    fn connection(&self) -> Option<RefMut<'_, redis::Connection>> {
+        if !self.persist {
+            return None;
+        }
+
+        let mut connection = self.connection.borrow_mut();
+
+        if connection.as_ref().is_some_and(|con| !redis::ConnectionLike::is_open(con)) {
+            *connection = None;
+        }
+
+        if connection.is_none() {
+            if self.reconnect_at.get().is_some_and(|at| Instant::now() < at) {
+                return None;
+            }
+
+            match self.redis.get_connection() {
+                Ok(con) => {
+                    if self.reconnect_at.take().is_some() {
+                        println!("{}, Redis available again", self.name);
+                    }
+
+                    *connection = Some(con);
+                }
+                Err(e) => {
+                    if self.reconnect_at.get().is_none() {
+                        println!("{}, Redis unavailable: {}", self.name, e);
+                    }
+
+                    self.reconnect_at.set(Some(Instant::now() + RECONNECT_DELAY));
+                    return None;
+                }
+            }
+        }
+
+        RefMut::filter_map(connection, Option::as_mut).ok()
+    }
+
+    // Push the running totals to redis.
+    fn report(&self) {
+        let score = Utc::now().timestamp();
+
+        let Some(mut con) = self.connection() else {
+            return;
+        };
+
+        let summary = format!(
+            "Total Profits: {:.3}, Total Losses {:.3}, Costs: {:.3}, Net: {:.3}",
+            self.profits,
+            self.losses,
+            self.costs_paid,
+            self.net_result()
+        );
+
+        let _: redis::RedisResult<()> = con.hset("trades:debile", &self.name, &summary);
+
+        if !self.family.is_empty() {
+            let _: redis::RedisResult<()> =
+                con.hset(format!("trades:debile:{}", self.family), &self.name, &summary);
+        }
+
+        if !self.symbol.is_empty() {
+            let _: redis::RedisResult<()> =
+                con.hset(format!("trades:debile:symbol:{}", self.symbol), &self.name, &summary);
+        }
+
+        let _: redis::RedisResult<()> = con.lpush(
+            "trades:history",
+            format!(
+                "S:{}, P:{:.1}, L{:.1}, T:{}",
+                self.name, self.profits, self.losses, score,
+            ),
+        );
+    }
+
+    // Price distance at which the open quantity loses `stop_loss_value_usd`.
+    fn stop_distance(&self) -> f64 {
+        self.stop_loss_value_usd / self.quantity
     }
 
     fn buy(&self, current_price: f64) -> [f64; 3] {
-        let stop_loss = current_price - self.stop_loss_value_usd;
-        let take_profit = current_price * (1.0 + (self.take_profit_percentage));
+        let stop_loss = self.rules.round_price(current_price - self.stop_distance());
+        let take_profit = self
+            .rules
+            .round_price(current_price * (1.0 + (self.take_profit_percentage)));
 
         // println!(
         //     "{}, Setting up Buy: {}, SL: {}, TP:{}",
-        //     self.symbol,
+        //     self.name,
         //     current_price, stop_loss, take_profit
         // );
 
@@ -226,12 +904,14 @@ impl SimpleTrading {
     }
 
     fn sell(&self, current_price: f64) -> [f64; 3] {
-        let stop_loss = current_price + self.stop_loss_value_usd;
-        let take_profit = current_price * (1.0 - self.take_profit_percentage);
+        let stop_loss = self.rules.round_price(current_price + self.stop_distance());
+        let take_profit = self
+            .rules
+            .round_price(current_price * (1.0 - self.take_profit_percentage));
 
         // println!(
         //     "{}, Setting up Sell: PRICE: {}, SL: {}, TP: {}",
-        //     self.symbol,
+        //     self.name,
         //     current_price, stop_loss, take_profit
         // );
 
@@ -239,6 +919,28 @@ impl SimpleTrading {
     }
 }
 
+impl Strategy for SimpleTrading {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn family(&self) -> &str {
+        &self.family
+    }
+
+    fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    fn on_tick(&mut self, price: f64, time: i64) -> Vec<Intent> {
+        self.tick(price, time)
+    }
+
+    fn state(&self) -> serde_json::Value {
+        serde_json::to_value(self.snapshot()).unwrap()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     macro_rules! trade_tests {
@@ -248,7 +950,7 @@ mod tests {
             fn $name() {
                 let (movements, expected_prices, expected_trade_status) = $value;
 
-                let mut trader = super::SimpleTrading::new("TEST".to_string(), movements[0], 0.0, 0.03 / 100.0, 5.0, 0.04);
+                let mut trader = super::SimpleTrading::new("TEST".to_string(), movements[0], 0.03 / 100.0, 5.0, 4.0);
 
                 for movement in movements.iter() {
                     trader.run(*movement);
@@ -271,15 +973,287 @@ mod tests {
         none: ([100_010.0, 100_020.0, 100_039.00, 100_039.0, 100_039.0], [0.0, 0.0, 0.0, 0.0], false),
         buy: ([100_000.0, 100_010.0, 100_020.0, 100_042.00, 100_042.0, 100_042.0], [100_042.0, 0.0, 0.0, 0.0], true),
         buy_tp: ([100_000.0, 100_010.0, 100_042.0, 100_042.00, 100_042.0 * 1.00031, 100_042.0 * 1.00031], [0.0, 0.0,  100_042.0 * 0.00031, 0.0], false),
-        buy_sl: ([100_000.0, 100_010.0, 100_042.0, 100_042.00, 100_042.0 * (1.0 - 0.00031), 100_042.0 * (1.0 - 0.00031)], [0.0, 0.0, 0.0, 100_042.0 * (0.00031)], false),
+        buy_sl: ([100_000.0, 100_010.0, 100_042.0, 100_042.00, 100_042.0 * (1.0 - 0.00031), 100_042.0 * (1.0 - 0.00031)], [0.0, 0.0, 0.0, 5.0], false),
         sell: ([100_000.0, 100_010.0, 100_020.0, 100_000.00- 42.0, 100_000.00- 42.0, 100_000.00- 42.0], [0.0, 100_000.00- 42.0, 0.0, 0.0], true),
         sell_tp: ([100_000.0, 100_000.0 - 10.0, 100_000.0 - 42.0, 100_000.0 - 42.0, (100_000.0 - 42.0) * (1.0 - 0.00031), (100_000.0 - 42.0) * (1.0 - 0.00031)], [0.0, 0.0,  (100_000.0 - 42.0) * 0.00031, 0.0], false),
-        sell_sl: ([100_000.0, 100_000.0 - 10.0, 100_000.0 - 42.0, 100_000.0 - 42.0, (100_000.0 - 42.0) * (1.0 + 0.00031), (100_000.0 - 42.0) * (1.0 + 0.00031)], [0.0, 0.0,  0.0, (100_000.0 - 42.0) * (0.00031)], false),
+        sell_sl: ([100_000.0, 100_000.0 - 10.0, 100_000.0 - 42.0, 100_000.0 - 42.0, (100_000.0 - 42.0) * (1.0 + 0.00031), (100_000.0 - 42.0) * (1.0 + 0.00031)], [0.0, 0.0,  0.0, 5.0], false),
+    }
+
+    macro_rules! exit_tests {
+        ($($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let (exits, movements, expected_results, expected_reasons) = $value;
+
+                let mut trader = super::SimpleTrading::new("TEST".to_string(), movements[0], 0.03 / 100.0, 5.0, 4.0);
+                trader.exits = exits;
+
+                let mut trades = Vec::new();
+
+                for movement in movements.iter() {
+                    trades.extend(trader.run(*movement));
+                }
+
+                let [tp, sl] = expected_results;
+                let reasons: Vec<ExitReason> = trades.iter().map(|trade| trade.exit_reason).collect();
+
+                assert_eq!(format!("{:.3}", trader.profits), format!("{:.3}", tp));
+                assert_eq!(format!("{:.3}", trader.losses), format!("{:.3}", sl));
+                assert_eq!(reasons, expected_reasons);
+                assert!(!trader.trade_active);
+            }
+        )*
+        }
+    }
+
+    use crate::exits::{BreakEven, ExitRules, PartialTakeProfit, TrailingStop};
+    use crate::ledger::ExitReason;
+
+    fn trailing(trailing_stop: TrailingStop) -> ExitRules {
+        ExitRules {
+            trailing_stop: Some(trailing_stop),
+            ..ExitRules::default()
+        }
+    }
+
+    exit_tests! {
+        // (exit rules, movement array, (take_profit, stop_loss), exit reasons)
+        buy_trailing_usd: (trailing(TrailingStop::Usd { value: 3.0 }), [100_000.0, 100_010.0, 100_042.0, 100_042.0, 100_050.0, 100_048.0, 100_046.0], [4.0, 0.0], vec![ExitReason::TrailingStop]),
+        buy_trailing_percentage: (trailing(TrailingStop::Percentage { value: 0.005 }), [100_000.0, 100_010.0, 100_042.0, 100_042.0, 100_065.0, 100_058.0], [16.0, 0.0], vec![ExitReason::TrailingStop]),
+        sell_trailing_usd: (trailing(TrailingStop::Usd { value: 3.0 }), [100_000.0, 99_990.0, 99_958.0, 99_958.0, 99_940.0, 99_946.0], [12.0, 0.0], vec![ExitReason::TrailingStop]),
+        buy_break_even: (ExitRules { break_even: Some(BreakEven { trigger_percentage: 0.01, offset_percentage: 0.0 }), ..ExitRules::default() }, [100_000.0, 100_010.0, 100_042.0, 100_042.0, 100_055.0, 100_042.0], [0.0, 0.0], vec![ExitReason::BreakEven]),
+        buy_partial_take_profit: (ExitRules { partial_take_profits: vec![PartialTakeProfit { percentage: 0.01, fraction: 0.5 }], ..ExitRules::default() }, [100_000.0, 100_010.0, 100_042.0, 100_042.0, 100_055.0, 100_080.0], [25.5, 0.0], vec![ExitReason::PartialTakeProfit, ExitReason::TakeProfit]),
+        sell_partial_then_stop: (ExitRules { partial_take_profits: vec![PartialTakeProfit { percentage: 0.01, fraction: 0.25 }], ..ExitRules::default() }, [100_000.0, 99_990.0, 99_958.0, 99_958.0, 99_945.0, 99_963.0], [3.25, 3.75], vec![ExitReason::PartialTakeProfit, ExitReason::StopLoss]),
+    }
+
+    #[test]
+    fn test_snapshot_restore() {
+        let mut trader = super::SimpleTrading::new("BTC".to_string(), 0.0, 0.03 / 100.0, 5.0, 4.0);
+
+        for movement in [100_000.0, 100_000.0, 100_050.0, 100_060.0] {
+            trader.run(movement);
+        }
+
+        trader.profits = 12.5;
+        trader.losses = 3.0;
+
+        let json = serde_json::to_string(&trader.snapshot()).unwrap();
+
+        let mut restored = super::SimpleTrading::new("BTC".to_string(), 0.0, 0.03 / 100.0, 5.0, 4.0);
+        restored.restore(serde_json::from_str(&json).unwrap());
+
+        assert_eq!(restored.snapshot(), trader.snapshot());
+        assert!(restored.trade_active);
+        assert_eq!(restored.buy_price, 100_060.0);
+
+        restored.run(100_060.0 * 1.0004);
+        assert!(!restored.trade_active);
+        assert_eq!(format!("{:.3}", restored.profits), format!("{:.3}", 12.5 + 100_060.0 * 0.0004));
+    }
+
+    #[test]
+    fn test_trade_record() {
+        let mut trader = super::SimpleTrading::new("BTC".to_string(), 0.0, 0.03 / 100.0, 5.0, 4.0);
+
+        let movements = [100_000.0, 100_000.0, 100_050.0, 100_060.0, 100_058.0, 100_080.0, 100_061.0, 100_054.0];
+        let mut trades = Vec::new();
+
+        for (time, movement) in movements.iter().enumerate() {
+            trades.extend(trader.run_at(*movement, time as i64 * 1_000));
+        }
+
+        assert_eq!(trades.len(), 1);
+
+        let trade = &trades[0];
+        assert_eq!(trade.bot, "BTC");
+        assert_eq!(trade.side, crate::ledger::Side::Long);
+        assert_eq!(trade.entry_price, 100_060.0);
+        assert_eq!(trade.entry_time, 3_000);
+        assert_eq!(trade.exit_price, 100_054.0);
+        assert_eq!(trade.exit_time, 7_000);
+        assert_eq!(trade.exit_reason, crate::ledger::ExitReason::StopLoss);
+        // the loss booked at the stop is capped to the 5$ stop loss.
+        assert_eq!(trade.gross_pnl, -5.0);
+        assert_eq!(trade.net_pnl, -5.0);
+        assert_eq!(trade.max_favourable_excursion, 20.0);
+        assert_eq!(trade.max_adverse_excursion, 6.0);
+        assert_eq!(trader.losses, 5.0);
+    }
+
+    #[test]
+    fn test_net_result_with_costs() {
+        let mut trader = super::SimpleTrading::new("BTC".to_string(), 0.0, 0.015 / 100.0, 5.0, 2.0);
+        trader.costs.fees.maker_percentage = 0.02;
+        trader.costs.fees.taker_percentage = 0.05;
+
+        let mut trades = Vec::new();
+
+        for movement in [100_000.0, 100_000.0, 100_030.0, 100_040.0, 100_060.0] {
+            trades.extend(trader.run(movement));
+        }
+
+        let trade = &trades[0];
+        assert_eq!(trade.exit_reason, crate::ledger::ExitReason::TakeProfit);
+        assert_eq!(trade.gross_pnl, 20.0);
+        assert_eq!(format!("{:.3}", trade.fees), format!("{:.3}", 100_040.0 * 0.0005 + 100_060.0 * 0.0002));
+        assert!(trade.net_pnl < 0.0);
+
+        assert_eq!(trader.profits, 20.0);
+        assert_eq!(format!("{:.3}", trader.net_result()), format!("{:.3}", trade.net_pnl));
+    }
+
+    #[test]
+    fn test_quantity_aware_pnl() {
+        let mut trader = super::SimpleTrading::new("BTC".to_string(), 0.0, 0.03 / 100.0, 5.0, 4.0);
+        trader.sizing = crate::sizing::PositionSizing::Notional(200.0);
+
+        let mut trades = Vec::new();
+
+        for movement in [100_000.0, 100_000.0, 100_050.0, 100_000.0] {
+            trades.extend(trader.run(movement));
+        }
+
+        // 200$ at 100k is 0.002 BTC, a 5$ stop loss is 2500$ away from the entry.
+        assert!(trader.trade_active);
+        assert_eq!(trader.quantity, 0.002);
+        assert_eq!(trader.buy_price, 100_000.0);
+        assert_eq!(trader.stop_loss, 97_500.0);
+
+        for movement in [99_000.0, 97_400.0] {
+            trades.extend(trader.run(movement));
+        }
+
+        let trade = &trades[0];
+        assert_eq!(trade.quantity, 0.002);
+        assert_eq!(trade.exit_reason, crate::ledger::ExitReason::StopLoss);
+        assert_eq!(format!("{:.3}", trade.gross_pnl), "-5.000");
+        assert_eq!(format!("{:.3}", trader.losses), "5.000");
+    }
+
+    #[test]
+    fn test_symbol_rules() {
+        let mut trader = super::SimpleTrading::new("ETH".to_string(), 0.0, 0.03 / 100.0, 5.0, 4.0);
+        trader.sizing = crate::sizing::PositionSizing::Notional(200.0);
+        trader.rules = crate::symbols::SymbolRules {
+            tick_size: 0.01,
+            lot_size: 0.001,
+            min_quantity: 0.01,
+        };
+
+        for movement in [3_456.78, 3_456.78, 3_458.3, 3_458.3] {
+            trader.run(movement);
+        }
+
+        // 200$ at 3458.3 is 0.05783 ETH, 0.057 in lots, the levels sit on the 0.01 tick.
+        assert!(trader.trade_active);
+        assert_eq!(trader.quantity, 0.057);
+        assert_eq!(trader.take_profit, 3_459.34);
+        assert_eq!(trader.stop_loss, 3_370.58);
+
+        // 20$ is below the minimum quantity, the bot keeps watching.
+        let mut trader = super::SimpleTrading::new("ETH".to_string(), 0.0, 0.03 / 100.0, 5.0, 4.0);
+        trader.sizing = crate::sizing::PositionSizing::Notional(20.0);
+        trader.rules.min_quantity = 0.01;
+
+        for movement in [3_456.78, 3_456.78, 3_458.3, 3_458.3] {
+            trader.run(movement);
+        }
+
+        assert!(!trader.trade_active);
+        assert_eq!(trader.watch_price, 3_458.3);
+    }
+
+    #[test]
+    fn test_account_equity() {
+        let mut trader = super::SimpleTrading::new("BTC".to_string(), 0.0, 0.03 / 100.0, 5.0, 4.0);
+        trader.account = crate::account::Account::new(1_000.0, true);
+        trader.sizing = crate::sizing::PositionSizing::BalancePercentage(50.0);
+        trader.leverage = 2.0;
+
+        for (time, movement) in [100_000.0, 100_000.0, 100_050.0, 100_000.0, 99_900.0].iter().enumerate() {
+            trader.run_at(*movement, time as i64 * 1_000);
+        }
+
+        // 1000$ * 50% * 2 = 1000$ of BTC at 100k.
+        assert_eq!(trader.quantity, 0.01);
+        assert_eq!(trader.unrealized_pnl(99_900.0), -1.0);
+        assert_eq!(trader.account.equity(), 999.0);
+        assert_eq!(trader.account.max_drawdown, 1.0);
+
+        trader.run_at(100_040.0, 5_000);
+
+        assert!(!trader.trade_active);
+        assert_eq!(format!("{:.3}", trader.account.balance), "1000.400");
+        assert_eq!(format!("{:.3}", trader.account.sizing_balance()), "1000.400");
+    }
+
+    // (seconds, price) ticks fed with their event time.
+    fn run_ticks(trader: &mut super::SimpleTrading, ticks: &[(i64, f64)]) -> Vec<crate::ledger::TradeRecord> {
+        let mut trades = Vec::new();
+
+        for (seconds, price) in ticks {
+            trades.extend(trader.run_at(*price, seconds * 1_000));
+        }
+
+        trades
+    }
+
+    #[test]
+    fn test_max_holding_time() {
+        let mut trader = super::SimpleTrading::new("BTC".to_string(), 0.0, 0.03 / 100.0, 5.0, 4.0);
+        trader.limits.max_holding_seconds = Some(60);
+
+        let trades = run_ticks(&mut trader, &[(0, 100_000.0), (1, 100_010.0), (2, 100_042.0), (3, 100_042.0), (30, 100_045.0)]);
+        assert!(trades.is_empty());
+        assert!(trader.trade_active);
+
+        let trades = run_ticks(&mut trader, &[(63, 100_044.0)]);
+        assert_eq!(trades[0].exit_reason, ExitReason::MaxHoldingTime);
+        assert_eq!(trades[0].gross_pnl, 2.0);
+        assert!(!trader.trade_active);
+    }
+
+    #[test]
+    fn test_stop_loss_cooldown() {
+        let mut trader = super::SimpleTrading::new("BTC".to_string(), 0.0, 0.03 / 100.0, 5.0, 4.0);
+        trader.limits.stop_loss_cooldown_seconds = 60;
+
+        let trades = run_ticks(&mut trader, &[(0, 100_000.0), (1, 100_010.0), (2, 100_042.0), (3, 100_042.0), (4, 100_030.0)]);
+        assert_eq!(trades[0].exit_reason, ExitReason::StopLoss);
+        assert_eq!(trader.cooldown_until, 64_000);
+
+        // a 0.05% move that would trigger a watch is ignored during the cooldown.
+        run_ticks(&mut trader, &[(5, 100_080.0), (6, 100_120.0), (64, 100_125.0), (65, 100_170.0)]);
+        assert!(!trader.trade_active);
+
+        run_ticks(&mut trader, &[(66, 100_180.0)]);
+        assert!(trader.trade_active);
+        assert_eq!(trader.buy_price, 100_180.0);
+    }
+
+    #[test]
+    fn test_daily_trade_cap() {
+        let mut trader = super::SimpleTrading::new("BTC".to_string(), 0.0, 0.03 / 100.0, 5.0, 4.0);
+        trader.limits.max_trades_per_day = Some(1);
+
+        let trades = run_ticks(&mut trader, &[(0, 100_000.0), (1, 100_010.0), (2, 100_042.0), (3, 100_042.0), (4, 100_080.0)]);
+        assert_eq!(trades[0].exit_reason, ExitReason::TakeProfit);
+        assert_eq!(trader.trades_today, 1);
+
+        run_ticks(&mut trader, &[(5, 100_200.0), (6, 100_300.0), (7, 100_400.0)]);
+        assert!(!trader.trade_active);
+
+        let day = crate::limits::DAY_MS / 1_000;
+        run_ticks(&mut trader, &[(day, 100_400.0), (day + 1, 100_500.0), (day + 2, 100_510.0)]);
+        assert!(trader.trade_active);
+        assert_eq!(trader.trading_day, 1);
+        assert_eq!(trader.trades_today, 1);
     }
 
     #[test]
     fn test_movement_percentage() {
-        let mut trader = super::SimpleTrading::new("BTC".to_string(), 100_000.0, 0.03 / 100.0, 5.0, 0.04);
+        let mut trader = super::SimpleTrading::new("BTC".to_string(), 100_000.0, 0.03 / 100.0, 5.0, 4.0);
 
         let movements = [100_001.0, 100_002.2, 150_000.00];
 
@@ -291,4 +1265,19 @@ mod tests {
 
         assert_eq!(movement_percentage, 50.0);
     }
+
+    #[test]
+    fn test_connection_retry() {
+        let mut trader = super::SimpleTrading::new("BTC".to_string(), 0.0, 0.03 / 100.0, 5.0, 4.0);
+        assert!(trader.connection().is_none());
+
+        // nothing listens there: the failure is remembered, not retried on the next tick.
+        trader.persist = true;
+        trader.redis = redis::Client::open("redis://127.0.0.1:1").unwrap();
+
+        assert!(trader.connection().is_none());
+        let reconnect_at = trader.reconnect_at.get().unwrap();
+        assert!(trader.connection().is_none());
+        assert_eq!(trader.reconnect_at.get(), Some(reconnect_at));
+    }
 }
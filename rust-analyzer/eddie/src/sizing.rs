@@ -0,0 +1,91 @@
+// How much of the base asset a bot buys or sells when it opens a trade.
+// The default is one unit, which is what SimpleTrading implicitly traded before sizing existed.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", content = "value", rename_all = "snake_case")]
+pub enum PositionSizing {
+    // Base asset quantity, e.g. 0.002 BTC like the trader's market_buy("BTCUSDT", 0.002).
+    Quantity(f64),
+    // Quote currency notional, e.g. 200 USDT worth of BTC.
+    Notional(f64),
+    // Percentage of the bot's virtual balance used as margin, multiplied by the leverage.
+    BalancePercentage(f64),
+}
+
+impl Default for PositionSizing {
+    fn default() -> Self {
+        PositionSizing::Quantity(1.0)
+    }
+}
+
+pub fn default_leverage() -> f64 {
+    1.0
+}
+
+impl PositionSizing {
+    pub fn validate(&self, leverage: f64, balance: f64) -> Result<(), String> {
+        if !leverage.is_finite() || !(1.0..=125.0).contains(&leverage) {
+            return Err(format!("leverage must be between 1 and 125, got {}", leverage));
+        }
+
+        match self {
+            PositionSizing::Quantity(value) | PositionSizing::Notional(value) => {
+                if !value.is_finite() || *value <= 0.0 {
+                    return Err(format!("sizing value must be positive, got {}", value));
+                }
+            }
+            PositionSizing::BalancePercentage(percentage) => {
+                if !percentage.is_finite() || *percentage <= 0.0 || *percentage > 100.0 {
+                    return Err(format!(
+                        "sizing percentage must be in ]0, 100], got {}",
+                        percentage
+                    ));
+                }
+
+                if balance <= 0.0 {
+                    return Err("balance_percentage sizing needs a positive balance".to_string());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Quantity of the base asset traded at `price`.
+    pub fn quantity(&self, price: f64, balance: f64, leverage: f64) -> f64 {
+        match self {
+            PositionSizing::Quantity(quantity) => *quantity,
+            PositionSizing::Notional(notional) => notional / price,
+            PositionSizing::BalancePercentage(percentage) => {
+                balance.max(0.0) * percentage / 100.0 * leverage / price
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quantity() {
+        assert_eq!(PositionSizing::default().quantity(100_000.0, 0.0, 1.0), 1.0);
+        assert_eq!(PositionSizing::Quantity(0.002).quantity(100_000.0, 0.0, 10.0), 0.002);
+        assert_eq!(PositionSizing::Notional(200.0).quantity(100_000.0, 0.0, 10.0), 0.002);
+        assert_eq!(
+            PositionSizing::BalancePercentage(10.0).quantity(100_000.0, 1_000.0, 5.0),
+            0.005
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(PositionSizing::Quantity(0.002).validate(1.0, 0.0).is_ok());
+        assert!(PositionSizing::Quantity(0.0).validate(1.0, 0.0).is_err());
+        assert!(PositionSizing::Notional(100.0).validate(200.0, 0.0).is_err());
+        assert!(PositionSizing::BalancePercentage(10.0).validate(1.0, 0.0).is_err());
+        assert!(PositionSizing::BalancePercentage(10.0).validate(1.0, 1_000.0).is_ok());
+    }
+}
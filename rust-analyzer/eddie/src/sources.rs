@@ -0,0 +1,138 @@
+// Market data sources. The live Binance streams, a recording (`replay::ReplaySource`) and the
+// synthetic feeds (`synthetic::SyntheticSource`) emit the same entries, which a Runner applies,
+// so the whole bot pipeline runs offline the way it runs on the exchange.
+
+use crate::ledger::TradeRecord;
+use crate::metrics::Metrics;
+use crate::reconnect::{self, Backoff};
+use crate::replay::Entry;
+use crate::runner::Runner;
+use crate::strategy::Intent;
+use binance::websockets::{WebSockets, WebsocketEvent};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+pub trait MarketSource {
+    // Calls `handle` with every entry until the source ends or `running` is cleared.
+    fn run(&mut self, running: &AtomicBool, handle: &mut dyn FnMut(Entry)) -> Result<(), String>;
+}
+
+// Kline streams of the exchange, reconnected with an exponential backoff when they drop.
+pub struct BinanceSource {
+    endpoints: Vec<String>,
+    backoff: Backoff,
+}
+
+impl BinanceSource {
+    pub fn new(endpoints: Vec<String>) -> Self {
+        Self {
+            endpoints,
+            backoff: Backoff::new(reconnect::INITIAL_BACKOFF, reconnect::MAX_BACKOFF),
+        }
+    }
+}
+
+impl MarketSource for BinanceSource {
+    // The binance crate returns its own (large) error type from the websocket callback.
+    #[allow(clippy::result_large_err)]
+    fn run(&mut self, running: &AtomicBool, handle: &mut dyn FnMut(Entry)) -> Result<(), String> {
+        while running.load(Ordering::Relaxed) {
+            let connected = Instant::now();
+
+            let mut web_socket = WebSockets::new(|event: WebsocketEvent| {
+                handle(Entry::Event(event));
+                Ok(())
+            });
+
+            match web_socket.connect_multiple_streams(&self.endpoints) {
+                Ok(()) => {
+                    if let Err(err) = web_socket.event_loop(running) {
+                        println!("VHAKM: Stream error: {:?}", err);
+                    }
+
+                    let _ = web_socket.disconnect();
+                }
+                Err(err) => println!("VHAKM: Could not connect: {:?}", err),
+            }
+
+            if !running.load(Ordering::Relaxed) {
+                break;
+            }
+
+            if connected.elapsed() >= reconnect::STABLE_CONNECTION {
+                self.backoff.reset();
+            }
+
+            let delay = self.backoff.next_delay();
+            println!("VHAKM: Reconnecting in {:?}", delay);
+            std::thread::sleep(delay);
+        }
+
+        Ok(())
+    }
+}
+
+// What a source fed to a runner: the kline events, their event time span and the closed trades.
+#[derive(Debug, Default)]
+pub struct Session {
+    pub events: usize,
+    pub from: i64,
+    pub to: i64,
+    pub trades: BTreeMap<String, Vec<TradeRecord>>,
+    // Starting balances of the bots.
+    pub balances: BTreeMap<String, f64>,
+}
+
+impl Session {
+    fn apply(&mut self, runner: &mut Runner, entry: Entry) {
+        match &entry {
+            Entry::Start(config) | Entry::Fleet(config) => {
+                for bot in &config.bots {
+                    self.balances.entry(bot.name.to_string()).or_insert(bot.balance);
+                }
+            }
+            Entry::Event(WebsocketEvent::Kline(kline)) => {
+                let time = kline.event_time as i64;
+
+                if self.events == 0 {
+                    self.from = time;
+                }
+
+                self.events += 1;
+                self.to = time;
+            }
+            _ => {}
+        }
+
+        for (name, intent) in runner.apply(entry) {
+            if let Intent::Close(trade) = intent {
+                self.trades.entry(name).or_default().push(trade);
+            }
+        }
+    }
+
+    pub fn metrics(&self) -> Vec<(String, Metrics)> {
+        self.trades
+            .iter()
+            .map(|(bot, trades)| {
+                let balance = self.balances.get(bot).copied().unwrap_or_default();
+                (bot.to_string(), Metrics::new(trades, balance, self.from, self.to))
+            })
+            .collect()
+    }
+}
+
+// Feed `source` to `runner` until it ends.
+pub fn drive(source: &mut dyn MarketSource, runner: &mut Runner) -> Result<Session, String> {
+    let mut session = Session::default();
+
+    for bot in &runner.fleet.config.bots {
+        session.balances.insert(bot.name.to_string(), bot.balance);
+    }
+
+    let running = AtomicBool::new(true);
+    source.run(&running, &mut |entry| session.apply(runner, entry))?;
+
+    Ok(session)
+}
@@ -0,0 +1,246 @@
+// Common interface of the trading bots driven by the websocket loop. A strategy receives every
+// price update (tick) and every kline update of its symbol and answers with the intents it acted
+// on, so the runner can report them whatever the strategy is.
+
+use crate::ledger::{Side, TradeRecord};
+use crate::processors::binance_hooks::binance_hooks::KlineData;
+use binance::model::KlineEvent;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Intent {
+    OpenLong { price: f64, quantity: f64 },
+    OpenShort { price: f64, quantity: f64 },
+    // Close (part of) the open position, booked in the ledger as `trade`.
+    Close(TradeRecord),
+}
+
+impl Intent {
+    pub fn open(side: Side, price: f64, quantity: f64) -> Self {
+        match side {
+            Side::Long => Intent::OpenLong { price, quantity },
+            Side::Short => Intent::OpenShort { price, quantity },
+        }
+    }
+
+    pub fn trade(self) -> Option<TradeRecord> {
+        match self {
+            Intent::Close(trade) => Some(trade),
+            _ => None,
+        }
+    }
+}
+
+// Kline update with parsed prices, `closed` is set on the final update of the bar.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Kline {
+    pub symbol: String,
+    pub interval: String,
+    pub open_time: i64,
+    pub close_time: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+    pub closed: bool,
+}
+
+impl From<&KlineEvent> for Kline {
+    fn from(event: &KlineEvent) -> Self {
+        Self {
+            symbol: event.symbol.to_string(),
+            interval: event.kline.interval.to_string(),
+            open_time: event.kline.open_time,
+            close_time: event.kline.close_time,
+            open: event.kline.open.parse().unwrap_or_default(),
+            high: event.kline.high.parse().unwrap_or_default(),
+            low: event.kline.low.parse().unwrap_or_default(),
+            close: event.kline.close.parse().unwrap_or_default(),
+            volume: event.kline.volume.parse().unwrap_or_default(),
+            closed: event.kline.is_final_bar,
+        }
+    }
+}
+
+impl Kline {
+    // Kline stored by Hooks::kline, only closed klines are stored so the open time is derived.
+    pub fn from_data(symbol: &str, interval: &str, data: &KlineData) -> Option<Self> {
+        let open_time = data.close_time + 1 - interval_ms(interval).unwrap_or(1_000);
+
+        Some(Self {
+            symbol: symbol.to_string(),
+            interval: interval.to_string(),
+            open_time,
+            close_time: data.close_time,
+            open: data.open.parse().ok()?,
+            high: data.high.parse().ok()?,
+            low: data.low.parse().ok()?,
+            close: data.close.parse().ok()?,
+            volume: data.volume.parse().ok()?,
+            closed: true,
+        })
+    }
+}
+
+// Interval of the klines whose close is the tick of every strategy.
+pub const TICK_INTERVAL: &str = "1s";
+
+pub const TICK_INTERVAL_MS: i64 = 1_000;
+
+// Length of a Binance kline interval (1s, 15m, 4h, 1d, 1w...), None for months or unknown ones.
+pub fn interval_ms(interval: &str) -> Option<i64> {
+    let (value, unit) = interval.split_at(interval.len().checked_sub(1)?);
+    let value: i64 = value.parse().ok()?;
+
+    let unit_ms = match unit {
+        "s" => 1_000,
+        "m" => 60_000,
+        "h" => 60 * 60_000,
+        "d" => 24 * 60 * 60_000,
+        "w" => 7 * 24 * 60 * 60_000,
+        _ => return None,
+    };
+
+    Some(value * unit_ms)
+}
+
+pub trait Strategy {
+    fn name(&self) -> &str;
+
+    fn family(&self) -> &str;
+
+    fn symbol(&self) -> &str;
+
+    // `time` is the event time in milliseconds, never the wall clock.
+    fn on_tick(&mut self, price: f64, time: i64) -> Vec<Intent>;
+
+    fn on_kline(&mut self, kline: &Kline, time: i64) -> Vec<Intent> {
+        Vec::new()
+    }
+
+    // JSON snapshot of the strategy state, as persisted for restarts.
+    fn state(&self) -> serde_json::Value;
+}
+
+// Feed a kline update to the strategies trading its symbol, the close price is their tick.
+// Returns the intents emitted, with the name of the strategy that emitted them.
+pub fn dispatch<'a>(
+    strategies: impl Iterator<Item = &'a mut dyn Strategy>,
+    kline: &Kline,
+    time: i64,
+) -> Vec<(String, Intent)> {
+    let mut intents = Vec::new();
+
+    for strategy in strategies.filter(|strategy| strategy.symbol() == kline.symbol) {
+        let name = strategy.name().to_string();
+
+        let emitted = strategy
+            .on_kline(kline, time)
+            .into_iter()
+            .chain(strategy.on_tick(kline.close, time));
+
+        intents.extend(emitted.map(|intent| (name.to_string(), intent)));
+    }
+
+    intents
+}
+
+// Feed a candle of another timeframe (subscribed or aggregated) to the strategies trading its
+// symbol, the ticks come from the base stream only.
+pub fn dispatch_candle<'a>(
+    strategies: impl Iterator<Item = &'a mut dyn Strategy>,
+    kline: &Kline,
+    time: i64,
+) -> Vec<(String, Intent)> {
+    let mut intents = Vec::new();
+
+    for strategy in strategies.filter(|strategy| strategy.symbol() == kline.symbol) {
+        let name = strategy.name().to_string();
+
+        intents.extend(
+            strategy
+                .on_kline(kline, time)
+                .into_iter()
+                .map(|intent| (name.to_string(), intent)),
+        );
+    }
+
+    intents
+}
+
+// One line per closed trade, opens are only visible in the state.
+pub fn report(intents: &[(String, Intent)]) {
+    for (name, intent) in intents {
+        if let Intent::Close(trade) = intent {
+            println!(
+                "{}, {:?} {:?} closed @ {:.2}, net {:.3}",
+                name, trade.side, trade.exit_reason, trade.exit_price, trade.net_pnl
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simple_trading::SimpleTrading;
+
+    fn kline(symbol: &str, close: f64) -> Kline {
+        Kline {
+            symbol: symbol.to_string(),
+            interval: "1s".to_string(),
+            open_time: 0,
+            close_time: 999,
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: 1.0,
+            closed: true,
+        }
+    }
+
+    #[test]
+    fn test_dispatch() {
+        let mut bots: Vec<SimpleTrading> = ["BTCUSDT", "ETHUSDT"]
+            .iter()
+            .map(|symbol| {
+                let mut bot = SimpleTrading::new(symbol.to_string(), 0.0, 0.03 / 100.0, 5.0, 4.0);
+                bot.symbol = symbol.to_string();
+                bot
+            })
+            .collect();
+
+        let mut intents = Vec::new();
+
+        for (time, price) in [100_000.0, 100_010.0, 100_042.0, 100_042.0, 100_080.0].iter().enumerate() {
+            let strategies = bots.iter_mut().map(|bot| bot as &mut dyn Strategy);
+            intents.extend(dispatch(strategies, &kline("BTCUSDT", *price), time as i64 * 1_000));
+        }
+
+        assert_eq!(intents.len(), 2);
+        assert_eq!(
+            intents[0],
+            (
+                "BTCUSDT".to_string(),
+                Intent::OpenLong {
+                    price: 100_042.0,
+                    quantity: 1.0
+                }
+            )
+        );
+
+        let trade = intents[1].1.clone().trade().unwrap();
+        assert_eq!(trade.exit_price, 100_080.0);
+        assert_eq!(bots[0].state()["profits"], 38.0);
+
+        assert_eq!(bots[1].last_price, 0.0);
+        assert_eq!(bots[1].state()["profits"], 0.0);
+
+        // candles of other timeframes are not ticks.
+        let strategies = bots.iter_mut().map(|bot| bot as &mut dyn Strategy);
+        assert!(dispatch_candle(strategies, &kline("BTCUSDT", 90_000.0), 5_000).is_empty());
+        assert_eq!(bots[0].last_price, 100_080.0);
+    }
+}
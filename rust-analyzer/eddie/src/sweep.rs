@@ -0,0 +1,422 @@
+// Parameter sweep: backtests a grid (or a random sample) of take profit, stop loss and watch
+// movement values of a fleet bot over the same klines, in parallel, and ranks them by net P&L.
+// Ranges are `from:to:step` or comma separated values, percentages are in percent as in fleet.toml.
+//
+//   eddie sweep --csv klines_btc_usdt.csv --base BTCUSDT:V1 \
+//       --take-profit 0.01:0.05:0.01 --stop-loss 2:10:2 --watch 0.02,0.04,0.09
+
+use crate::backtest::{self, BacktestArgs, Summary};
+use crate::fleet::{self, Bot, BotConfig, FleetConfig};
+use crate::limits::DAY_MS;
+use crate::strategy::Kline;
+use serde::Serialize;
+
+const DEFAULT_TOP: usize = 20;
+
+// `from:to:step` (inclusive) or `a,b,c`.
+pub fn parse_range(range: &str) -> Result<Vec<f64>, String> {
+    let number = |value: &str| {
+        value
+            .trim()
+            .parse::<f64>()
+            .map_err(|e| format!("{:?}: {}", value, e))
+    };
+
+    let parts: Vec<&str> = range.split(':').collect();
+
+    if parts.len() != 3 {
+        return range.split(',').map(number).collect();
+    }
+
+    let (from, to, step) = (number(parts[0])?, number(parts[1])?, number(parts[2])?);
+
+    if step <= 0.0 || to < from {
+        return Err(format!("{:?} needs from <= to and a positive step", range));
+    }
+
+    let count = ((to - from) / step + 1e-9).floor() as usize + 1;
+
+    // rounded so 0.01:0.05:0.01 gives 0.03 and not 0.030000000000000002.
+    Ok((0..count)
+        .map(|index| ((from + step * index as f64) * 1e8).round() / 1e8)
+        .collect())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Parameters {
+    pub take_profit_percentage: f64,
+    pub stop_loss_usd: f64,
+    pub watch_movement_percentage: f64,
+}
+
+impl Parameters {
+    pub fn bot_config(&self, template: &BotConfig) -> BotConfig {
+        BotConfig {
+            name: format!(
+                "sweep:{}:{}:{}",
+                self.take_profit_percentage, self.stop_loss_usd, self.watch_movement_percentage
+            ),
+            take_profit_percentage: self.take_profit_percentage,
+            stop_loss_usd: self.stop_loss_usd,
+            watch_movement_percentage: self.watch_movement_percentage,
+            signal: None,
+            ..template.clone()
+        }
+    }
+}
+
+pub fn grid(take_profits: &[f64], stop_losses: &[f64], watches: &[f64]) -> Vec<Parameters> {
+    let mut parameters = Vec::new();
+
+    for take_profit_percentage in take_profits {
+        for stop_loss_usd in stop_losses {
+            for watch_movement_percentage in watches {
+                parameters.push(Parameters {
+                    take_profit_percentage: *take_profit_percentage,
+                    stop_loss_usd: *stop_loss_usd,
+                    watch_movement_percentage: *watch_movement_percentage,
+                });
+            }
+        }
+    }
+
+    parameters
+}
+
+// SplitMix64, a seeded sample is reproducible without pulling a random crate.
+pub struct SplitMix64(pub u64);
+
+impl SplitMix64 {
+    pub fn next_f64(&mut self) -> f64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // Uniform between the smallest and the largest value, 4 decimals.
+    fn within(&mut self, values: &[f64]) -> f64 {
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+
+        ((min + (max - min) * self.next_f64()) * 1e4).round() / 1e4
+    }
+}
+
+pub fn random_sample(
+    take_profits: &[f64],
+    stop_losses: &[f64],
+    watches: &[f64],
+    count: usize,
+    seed: u64,
+) -> Vec<Parameters> {
+    let mut random = SplitMix64(seed);
+
+    (0..count)
+        .map(|_| Parameters {
+            take_profit_percentage: random.within(take_profits),
+            stop_loss_usd: random.within(stop_losses),
+            watch_movement_percentage: random.within(watches),
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SweepResult {
+    pub parameters: Parameters,
+    pub summary: Summary,
+    pub trades_per_day: f64,
+}
+
+// Days covered by the klines, at least one second.
+pub fn dataset_days(klines: &[Kline]) -> f64 {
+    match (klines.first(), klines.last()) {
+        (Some(first), Some(last)) => ((last.close_time - first.open_time).max(1_000)) as f64 / DAY_MS as f64,
+        _ => 0.0,
+    }
+}
+
+fn evaluate(template: &BotConfig, parameters: Parameters, klines: &[Kline]) -> SweepResult {
+    let mut trader = parameters.bot_config(template).build();
+    trader.persist = false;
+
+    let mut bots = [Bot::Movement(trader)];
+    let report = backtest::run(&mut bots, klines).remove(0);
+    let days = dataset_days(klines);
+
+    SweepResult {
+        parameters,
+        trades_per_day: if days > 0.0 {
+            report.summary.trades as f64 / days
+        } else {
+            0.0
+        },
+        summary: report.summary,
+    }
+}
+
+// `evaluate` of every item on `threads` threads, in the order of `items`.
+pub fn parallel_map<T: Sync, R: Send>(
+    items: &[T],
+    threads: usize,
+    evaluate: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    let chunk = items.len().div_ceil(threads.max(1)).max(1);
+    let evaluate = &evaluate;
+
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = items
+            .chunks(chunk)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(evaluate).collect::<Vec<_>>()))
+            .collect();
+
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    })
+}
+
+// Backtest every parameter set on `threads` threads, best net P&L first.
+pub fn sweep(
+    template: &BotConfig,
+    parameters: &[Parameters],
+    klines: &[Kline],
+    threads: usize,
+) -> Vec<SweepResult> {
+    let mut results = parallel_map(parameters, threads, |parameters| {
+        evaluate(template, *parameters, klines)
+    });
+
+    results.sort_by(|a, b| b.summary.net_pnl.total_cmp(&a.summary.net_pnl));
+
+    results
+}
+
+pub fn print_results(results: &[SweepResult], top: usize) {
+    println!(
+        "{:>4} {:>8} {:>8} {:>8} {:>7} {:>9} {:>7} {:>12} {:>10}",
+        "rank", "tp %", "sl $", "watch %", "trades", "trades/d", "win %", "net", "max dd"
+    );
+
+    for (rank, result) in results.iter().take(top).enumerate() {
+        println!(
+            "{:>4} {:>8} {:>8} {:>8} {:>7} {:>9.1} {:>7.1} {:>12.3} {:>10.3}",
+            rank + 1,
+            result.parameters.take_profit_percentage,
+            result.parameters.stop_loss_usd,
+            result.parameters.watch_movement_percentage,
+            result.summary.trades,
+            result.trades_per_day,
+            result.summary.win_rate,
+            result.summary.net_pnl,
+            result.summary.max_drawdown
+        );
+    }
+}
+
+// Bot of the fleet the parameters are applied to, `base` or the first bot of the symbol. The
+// base bot must trade the symbol of the data.
+pub fn base_bot<'a>(
+    config: &'a FleetConfig,
+    base: Option<&str>,
+    data: &BacktestArgs,
+) -> Result<&'a BotConfig, String> {
+    let bot = config
+        .bots
+        .iter()
+        .find(|bot| match base {
+            Some(name) => bot.name == name,
+            None => bot.symbol == data.symbol,
+        })
+        .ok_or_else(|| format!("no base bot {:?} for {} in {}", base, data.symbol, data.fleet))?;
+
+    if bot.symbol != data.symbol {
+        return Err(format!(
+            "base bot {} trades {}, not {}",
+            bot.name, bot.symbol, data.symbol
+        ));
+    }
+
+    Ok(bot)
+}
+
+pub fn run_cli(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut take_profits = None;
+    let mut stop_losses = None;
+    let mut watches = None;
+    let mut random = None;
+    let mut seed = 1;
+    let mut base = None;
+    let mut top = DEFAULT_TOP;
+    let mut threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+    let mut output = None;
+
+    // everything else selects the data, as for `eddie backtest`.
+    let mut data_args = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", flag))?
+            .to_string();
+
+        match flag.as_str() {
+            "--take-profit" => take_profits = Some(parse_range(&value)?),
+            "--stop-loss" => stop_losses = Some(parse_range(&value)?),
+            "--watch" => watches = Some(parse_range(&value)?),
+            "--random" => random = Some(value.parse::<usize>()?),
+            "--seed" => seed = value.parse()?,
+            "--base" => base = Some(value),
+            "--top" => top = value.parse()?,
+            "--threads" => threads = value.parse()?,
+            "--output" => output = Some(value),
+            _ => data_args.extend([flag.to_string(), value]),
+        }
+    }
+
+    let data = BacktestArgs::parse(&data_args)?;
+    let config = fleet::load_fleet_config(&data.fleet)?;
+
+    let template = base_bot(&config, base.as_deref(), &data)?;
+
+    let take_profits = take_profits.unwrap_or(vec![template.take_profit_percentage]);
+    let stop_losses = stop_losses.unwrap_or(vec![template.stop_loss_usd]);
+    let watches = watches.unwrap_or(vec![template.watch_movement_percentage]);
+
+    let parameters = match random {
+        Some(count) => random_sample(&take_profits, &stop_losses, &watches, count, seed),
+        None => grid(&take_profits, &stop_losses, &watches),
+    };
+
+    if let Some(invalid) = parameters
+        .iter()
+        .find_map(|parameters| parameters.bot_config(template).validate().err())
+    {
+        return Err(invalid.into());
+    }
+
+    let klines = data.klines()?;
+
+    println!(
+        "VHAKM: Sweeping {} parameter sets of {} on {} {} klines ({:.1} days) with {} threads",
+        parameters.len(),
+        template.name,
+        klines.len(),
+        data.interval,
+        dataset_days(&klines),
+        threads
+    );
+
+    let results = sweep(template, &parameters, &klines, threads);
+    print_results(&results, top);
+
+    if let Some(path) = &output {
+        std::fs::write(path, serde_json::to_string_pretty(&results)?)?;
+        println!("VHAKM: Results written to {}", path);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("0.01:0.05:0.01").unwrap(), vec![0.01, 0.02, 0.03, 0.04, 0.05]);
+        assert_eq!(parse_range("2, 5,10").unwrap(), vec![2.0, 5.0, 10.0]);
+        assert_eq!(parse_range("0.09").unwrap(), vec![0.09]);
+        assert!(parse_range("5:1:1").is_err());
+        assert!(parse_range("a,b").is_err());
+    }
+
+    #[test]
+    fn test_grid_and_random_sample() {
+        assert_eq!(grid(&[0.01, 0.02], &[2.0, 5.0, 10.0], &[0.04]).len(), 6);
+
+        let sample = random_sample(&[0.01, 0.05], &[2.0, 10.0], &[0.02, 0.09], 50, 7);
+        assert_eq!(sample.len(), 50);
+        assert_eq!(sample, random_sample(&[0.01, 0.05], &[2.0, 10.0], &[0.02, 0.09], 50, 7));
+        assert!(sample.iter().all(|p| (0.01..=0.05).contains(&p.take_profit_percentage)
+            && (2.0..=10.0).contains(&p.stop_loss_usd)
+            && (0.02..=0.09).contains(&p.watch_movement_percentage)));
+    }
+
+    #[test]
+    fn test_base_bot_symbol() {
+        let config = FleetConfig {
+            bots: ["BTCUSDT", "ETHUSDT"]
+                .iter()
+                .map(|symbol| {
+                    serde_json::from_value(serde_json::json!({
+                        "name": format!("test:{}:A", symbol), "family": "vhakm", "symbol": symbol,
+                        "take_profit_percentage": 0.03, "stop_loss_usd": 5.0,
+                        "watch_movement_percentage": 4.0
+                    }))
+                    .unwrap()
+                })
+                .collect(),
+            ..Default::default()
+        };
+
+        let data = BacktestArgs::parse(&["--symbol".to_string(), "ethusdt".to_string()]).unwrap();
+
+        assert_eq!(base_bot(&config, None, &data).unwrap().name, "test:ETHUSDT:A");
+        assert_eq!(base_bot(&config, Some("test:ETHUSDT:A"), &data).unwrap().symbol, "ETHUSDT");
+        assert_eq!(
+            base_bot(&config, Some("test:BTCUSDT:A"), &data).unwrap_err(),
+            "base bot test:BTCUSDT:A trades BTCUSDT, not ETHUSDT"
+        );
+        assert!(base_bot(&config, Some("test:SOLUSDT:A"), &data).is_err());
+    }
+
+    #[test]
+    fn test_sweep_ranks_by_net() {
+        let template: BotConfig = serde_json::from_str(
+            r#"{"name": "test:BTCUSDT:A", "family": "vhakm", "symbol": "BTCUSDT", "take_profit_percentage": 0.03,
+                "stop_loss_usd": 5.0, "watch_movement_percentage": 4.0}"#,
+        )
+        .unwrap();
+
+        let klines: Vec<Kline> = [100_000.0, 100_010.0, 100_042.0, 100_042.0, 100_080.0, 100_150.0, 100_100.0]
+            .iter()
+            .enumerate()
+            .map(|(second, close)| Kline {
+                symbol: "BTCUSDT".to_string(),
+                interval: "1s".to_string(),
+                open_time: second as i64 * 1_000,
+                close_time: second as i64 * 1_000 + 999,
+                open: *close,
+                high: *close,
+                low: *close,
+                close: *close,
+                volume: 1.0,
+                closed: true,
+            })
+            .collect();
+
+        let parameters = grid(&[0.03, 0.1], &[5.0], &[4.0, 50.0]);
+        let results = sweep(&template, &parameters, &klines, 3);
+
+        assert_eq!(results.len(), 4);
+        assert!(results.windows(2).all(|pair| pair[0].summary.net_pnl >= pair[1].summary.net_pnl));
+
+        // the 0.1% take profit rides the move from 100042 to 100150, a 0.5% watch (50) never trades.
+        assert_eq!(results[0].parameters.take_profit_percentage, 0.1);
+        assert_eq!(results[0].summary.net_pnl, 108.0);
+        assert_eq!(results[3].summary.trades, 0);
+
+        let sequential = sweep(&template, &parameters, &klines, 1);
+        assert_eq!(
+            serde_json::to_string(&results).unwrap(),
+            serde_json::to_string(&sequential).unwrap()
+        );
+    }
+}
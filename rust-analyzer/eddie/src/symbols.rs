@@ -0,0 +1,84 @@
+// Exchange rules of a traded symbol, as in the Binance PRICE_FILTER and LOT_SIZE filters.
+// Stops and take profits are rounded to the tick size, position and partial quantities down to
+// the lot size. A zero size disables the rounding, which is the default for unlisted symbols.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct SymbolRules {
+    // Price increment, e.g. 0.1 USDT for BTCUSDT futures.
+    #[serde(default)]
+    pub tick_size: f64,
+    // Quantity increment of the base asset, e.g. 0.001 BTC.
+    #[serde(default)]
+    pub lot_size: f64,
+    // Smallest quantity accepted, smaller positions are not opened.
+    #[serde(default)]
+    pub min_quantity: f64,
+}
+
+// Removes the binary noise left by a multiplication, 0.1 * 3 is 0.30000000000000004.
+fn clean(value: f64) -> f64 {
+    (value * 1e10).round() / 1e10
+}
+
+impl SymbolRules {
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, value) in [
+            ("tick_size", self.tick_size),
+            ("lot_size", self.lot_size),
+            ("min_quantity", self.min_quantity),
+        ] {
+            if !value.is_finite() || value < 0.0 {
+                return Err(format!("{} must be zero or positive, got {}", name, value));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn round_price(&self, price: f64) -> f64 {
+        if self.tick_size > 0.0 {
+            clean((price / self.tick_size).round() * self.tick_size)
+        } else {
+            price
+        }
+    }
+
+    pub fn round_quantity(&self, quantity: f64) -> f64 {
+        if self.lot_size > 0.0 {
+            clean((quantity / self.lot_size + 1e-9).floor() * self.lot_size)
+        } else {
+            quantity
+        }
+    }
+
+    pub fn tradable(&self, quantity: f64) -> bool {
+        quantity > 0.0 && quantity >= self.min_quantity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rounding() {
+        let rules = SymbolRules {
+            tick_size: 0.01,
+            lot_size: 0.001,
+            min_quantity: 0.002,
+        };
+
+        assert_eq!(rules.round_price(3_456.789), 3_456.79);
+        assert_eq!(rules.round_quantity(200.0 / 3_456.789), 0.057);
+        assert_eq!(rules.round_quantity(0.003), 0.003);
+        assert!(rules.tradable(0.002));
+        assert!(!rules.tradable(rules.round_quantity(0.0019)));
+
+        let none = SymbolRules::default();
+        assert_eq!(none.round_price(3_456.789), 3_456.789);
+        assert_eq!(none.round_quantity(0.0571), 0.0571);
+        assert!(SymbolRules { lot_size: -1.0, ..none }.validate().is_err());
+    }
+}
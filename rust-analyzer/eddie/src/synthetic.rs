@@ -0,0 +1,261 @@
+// Synthetic market data for offline runs and tests: klines of one symbol following a chain of
+// segments, each a random walk, a geometric Brownian motion or scripted closes, with gaps where
+// no kline is emitted. Chaining segments gives regime switches, a scripted drop a flash crash.
+// The paths are seeded, the same source always emits the same klines.
+
+use crate::replay::Entry;
+use crate::sources::MarketSource;
+use crate::strategy::{interval_ms, Kline, TICK_INTERVAL};
+use crate::sweep::SplitMix64;
+use binance::model::{self, KlineEvent};
+use binance::websockets::WebsocketEvent;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    // `count` closes moving by up to `step` quote currency each, up or down.
+    RandomWalk { count: usize, step: f64 },
+    // `count` closes of a geometric Brownian motion, drift and volatility per kline.
+    Gbm { count: usize, drift: f64, volatility: f64 },
+    Scripted(Vec<f64>),
+    // `count` klines missing from the stream.
+    Gap(usize),
+}
+
+pub struct SyntheticSource {
+    pub symbol: String,
+    pub interval: String,
+    // Open time of the first kline.
+    pub start: i64,
+    pub price: f64,
+    pub seed: u64,
+    pub segments: Vec<Segment>,
+}
+
+// Prices stay above a cent whatever the path.
+const MIN_PRICE: f64 = 0.01;
+
+fn cents(price: f64) -> f64 {
+    ((price * 100.0).round() / 100.0).max(MIN_PRICE)
+}
+
+impl SyntheticSource {
+    pub fn new(symbol: &str, price: f64, seed: u64) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            interval: TICK_INTERVAL.to_string(),
+            start: 0,
+            price,
+            seed,
+            segments: Vec::new(),
+        }
+    }
+
+    pub fn then(mut self, segment: Segment) -> Self {
+        self.segments.push(segment);
+        self
+    }
+
+    // Closes of every segment, None for the klines of a gap.
+    fn closes(&self) -> Vec<Option<f64>> {
+        let mut random = SplitMix64(self.seed);
+        let mut price = self.price;
+        let mut closes = Vec::new();
+
+        for segment in &self.segments {
+            match segment {
+                Segment::RandomWalk { count, step } => {
+                    for _ in 0..*count {
+                        price = cents(price + step * (2.0 * random.next_f64() - 1.0));
+                        closes.push(Some(price));
+                    }
+                }
+                Segment::Gbm {
+                    count,
+                    drift,
+                    volatility,
+                } => {
+                    for _ in 0..*count {
+                        // Box-Muller, 1 - u keeps the logarithm finite.
+                        let u = 1.0 - random.next_f64();
+                        let v = random.next_f64();
+                        let angle = 2.0 * std::f64::consts::PI * v;
+                        let normal = (-2.0 * u.ln()).sqrt() * angle.cos();
+
+                        let growth = drift - volatility * volatility / 2.0 + volatility * normal;
+                        price = cents(price * growth.exp());
+                        closes.push(Some(price));
+                    }
+                }
+                Segment::Scripted(prices) => {
+                    for close in prices {
+                        price = *close;
+                        closes.push(Some(price));
+                    }
+                }
+                Segment::Gap(count) => closes.extend(std::iter::repeat_n(None, *count)),
+            }
+        }
+
+        closes
+    }
+
+    // Closed klines opening on the previous close, one interval apart.
+    pub fn klines(&self) -> Vec<Kline> {
+        let length = interval_ms(&self.interval).unwrap_or(1_000);
+        let mut open = self.price;
+        let mut klines = Vec::new();
+
+        for (index, close) in self.closes().into_iter().enumerate() {
+            let Some(close) = close else { continue };
+            let open_time = self.start + index as i64 * length;
+
+            klines.push(Kline {
+                symbol: self.symbol.to_string(),
+                interval: self.interval.to_string(),
+                open_time,
+                close_time: open_time + length - 1,
+                open,
+                high: open.max(close),
+                low: open.min(close),
+                close,
+                volume: 1.0,
+                closed: true,
+            });
+
+            open = close;
+        }
+
+        klines
+    }
+}
+
+// The websocket event of a closed kline, received when it closes.
+pub fn event(kline: &Kline) -> WebsocketEvent {
+    WebsocketEvent::Kline(KlineEvent {
+        event_type: "kline".to_string(),
+        event_time: (kline.close_time + 1) as u64,
+        symbol: kline.symbol.to_string(),
+        kline: model::Kline {
+            open_time: kline.open_time,
+            close_time: kline.close_time,
+            symbol: kline.symbol.to_string(),
+            interval: kline.interval.to_string(),
+            first_trade_id: 0,
+            last_trade_id: 0,
+            open: kline.open.to_string(),
+            close: kline.close.to_string(),
+            high: kline.high.to_string(),
+            low: kline.low.to_string(),
+            volume: kline.volume.to_string(),
+            number_of_trades: 0,
+            is_final_bar: kline.closed,
+            quote_asset_volume: "0".to_string(),
+            taker_buy_base_asset_volume: "0".to_string(),
+            taker_buy_quote_asset_volume: "0".to_string(),
+            ignore_me: String::new(),
+        },
+    })
+}
+
+impl MarketSource for SyntheticSource {
+    fn run(&mut self, running: &AtomicBool, handle: &mut dyn FnMut(Entry)) -> Result<(), String> {
+        for kline in self.klines() {
+            if !running.load(Ordering::Relaxed) {
+                break;
+            }
+
+            handle(Entry::Event(event(&kline)));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fleet::{Fleet, FleetConfig};
+    use crate::ledger::{ExitReason, Side};
+    use crate::runner::Runner;
+    use crate::sources;
+
+    const FLEET: &str = r#"
+        aggregated_intervals = ["1m"]
+
+        [[bots]]
+        name = "test:BTCUSDT:A"
+        family = "vhakm"
+        symbol = "BTCUSDT"
+        take_profit_percentage = 0.03
+        stop_loss_usd = 50.0
+        watch_movement_percentage = 4.0
+    "#;
+
+    fn runner() -> Runner {
+        let config: FleetConfig = config::Config::builder()
+            .add_source(config::File::from_str(FLEET, config::FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        let symbols = config.traded_symbols();
+        Runner::new(Fleet::new(config), symbols).without_persistence()
+    }
+
+    #[test]
+    fn test_paths() {
+        let source = SyntheticSource::new("BTCUSDT", 100_000.0, 7)
+            .then(Segment::RandomWalk { count: 100, step: 20.0 })
+            .then(Segment::Gap(10))
+            .then(Segment::Gbm { count: 100, drift: 0.0, volatility: 0.001 });
+
+        let klines = source.klines();
+        assert_eq!(klines.len(), 200);
+        assert_eq!(klines, source.klines());
+        assert_ne!(klines, SyntheticSource { seed: 8, ..source }.klines());
+
+        // the gap leaves 10 seconds without kline.
+        assert_eq!(klines[100].open_time - klines[99].open_time, 11_000);
+        assert!(klines.windows(2).all(|pair| pair[1].open == pair[0].close));
+        let walk = &klines[..100];
+        assert!(walk.windows(2).all(|pair| (pair[1].close - pair[0].close).abs() <= 20.01));
+        assert!(klines.iter().all(|kline| kline.low > 0.0 && kline.high >= kline.low));
+    }
+
+    #[test]
+    fn test_flash_crash() {
+        // a long opened on the rise is stopped out by the crash, the short opened on the crash by
+        // the rebound, both losses capped to the 50$ stop loss.
+        let mut source = SyntheticSource::new("BTCUSDT", 100_000.0, 1)
+            .then(Segment::Scripted(vec![100_000.0, 100_050.0, 100_050.0]))
+            .then(Segment::Scripted(vec![99_000.0, 95_000.0, 99_500.0, 100_000.0]));
+
+        let session = sources::drive(&mut source, &mut runner()).unwrap();
+        let trades = &session.trades["test:BTCUSDT:A"];
+
+        assert_eq!(session.events, 7);
+        assert_eq!(trades.len(), 2);
+        assert_eq!((trades[0].side, trades[0].exit_reason), (Side::Long, ExitReason::StopLoss));
+        assert_eq!((trades[0].entry_price, trades[0].net_pnl), (100_050.0, -50.0));
+        assert_eq!((trades[1].side, trades[1].exit_reason), (Side::Short, ExitReason::StopLoss));
+        assert_eq!((trades[1].entry_price, trades[1].net_pnl), (99_500.0, -50.0));
+    }
+
+    #[test]
+    fn test_gap() {
+        // 20 seconds missing from the stream, trading resumes after them.
+        let mut source = SyntheticSource::new("BTCUSDT", 100_000.0, 1)
+            .then(Segment::Scripted(vec![100_000.0; 50]))
+            .then(Segment::Gap(20))
+            .then(Segment::Scripted(vec![100_050.0, 100_050.0, 100_200.0]));
+
+        let mut runner = runner();
+        let session = sources::drive(&mut source, &mut runner).unwrap();
+
+        assert_eq!(session.events, 53);
+        assert_eq!(session.trades["test:BTCUSDT:A"][0].exit_reason, ExitReason::TakeProfit);
+        assert_eq!(session.to, 73_000);
+    }
+}
@@ -1,16 +1,18 @@
 pub mod strategy_builder {
+    use crate::order_flow::OrderFlow;
     use redis::{Client, Commands};
     use serde::{Deserialize, Serialize};
     use std::collections::HashMap;
 
-    #[derive(Debug, Serialize, Deserialize)]
+    #[allow(clippy::upper_case_acronyms)]
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
     pub enum Signal {
         BUY,
         SELL,
         HOLD,
     }
 
-    #[derive(Debug, Serialize, Deserialize)]
+    #[derive(Debug, Clone, Serialize, Deserialize)]
     pub struct IndicatorData {
         ma: f64,
         ema: f64,
@@ -20,29 +22,50 @@ pub mod strategy_builder {
         ml1: MLSignals,
         ml_volume: MLSignals,
         bollinger_bands: Vec<Vec<f64>>,
-        timestamp: i64,
+        pub(crate) timestamp: i64,
     }
 
-    #[derive(Debug, Serialize, Deserialize)]
+    impl IndicatorData {
+        // The timestamp may be in seconds, klines are in milliseconds.
+        pub(crate) fn time_ms(&self) -> i64 {
+            if self.timestamp < 100_000_000_000 {
+                self.timestamp * 1_000
+            } else {
+                self.timestamp
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
     pub struct MLSignals {
         hold: i32,
         buy: i32,
         sell: i32,
     }
 
-    #[derive(Debug, Serialize, Deserialize)]
+    #[derive(Debug, Clone, Serialize, Deserialize)]
     pub struct IndicatorConfig {
         pub(crate) period: i32,
         pub(crate) lookback: Option<i32>,
         pub(crate) threshold: f64,
         pub(crate) weight: f64,
+        // Sorted set read by the order_flow indicator, e.g. variations:order_flow:BTCUSDT:1m.
+        #[serde(default)]
+        pub(crate) key: Option<String>,
     }
 
-    #[derive(Debug, Serialize, Deserialize)]
+    #[derive(Debug, Clone, Serialize, Deserialize)]
     pub struct StrategyConfig {
         pub(crate) indicators: HashMap<String, IndicatorConfig>,
     }
 
+    impl StrategyConfig {
+        // Sorted set of the order_flow indicator, if the strategy weighs one.
+        pub fn order_flow_key(&self) -> Option<&str> {
+            self.indicators.get("order_flow")?.key.as_deref()
+        }
+    }
+
     pub struct StrategyBuilder {
         redis_client: Client,
         config: StrategyConfig,
@@ -58,12 +81,61 @@ pub mod strategy_builder {
 
         pub fn evaluate(&self, base_key: &str) -> Result<Signal, Box<dyn std::error::Error>> {
             let mut conn = self.redis_client.get_connection()?;
+
+            let mut flows = self.redis_client.get_connection()?;
+
+            self.evaluate_with(
+                |period, lookback| self.get_indicator_data(&mut conn, base_key, period, lookback),
+                |key, lookback| self.get_order_flow(&mut flows, key, lookback),
+            )
+        }
+
+        // Same as evaluate, on an in-memory history ordered newest first (backtests, replays). The
+        // history has no order flow, strategies with an order_flow indicator are refused.
+        pub fn evaluate_history(
+            &self,
+            history: &[IndicatorData],
+        ) -> Result<Signal, Box<dyn std::error::Error>> {
+            self.evaluate_with(
+                |period, lookback| {
+                    let period = period.max(1) as usize;
+                    let range = period * lookback.unwrap_or(1).max(1) as usize;
+
+                    Ok(history.iter().take(range).step_by(period).cloned().collect())
+                },
+                |key, _| Err(format!("no order flow history for {}", key).into()),
+            )
+        }
+
+        fn evaluate_with(
+            &self,
+            mut indicator_data: impl FnMut(i32, Option<i32>) -> Result<Vec<IndicatorData>, Box<dyn std::error::Error>>,
+            mut order_flow: impl FnMut(&str, Option<i32>) -> Result<Vec<OrderFlow>, Box<dyn std::error::Error>>,
+        ) -> Result<Signal, Box<dyn std::error::Error>> {
             let mut buy_score = 0.0;
             let mut sell_score = 0.0;
 
-            for (indicator, config) in &self.config.indicators {
-                let data =
-                    self.get_indicator_data(&mut conn, base_key, config.period, config.lookback)?;
+            // sorted so the scores add up in the same order on every run.
+            let mut indicators: Vec<_> = self.config.indicators.iter().collect();
+            indicators.sort_by(|a, b| a.0.cmp(b.0));
+
+            for (indicator, config) in indicators {
+                // taker flow of the aggregated trades, stored apart from the indicator data.
+                if indicator == "order_flow" {
+                    if let Some(key) = &config.key {
+                        let flows = order_flow(key, config.lookback)?;
+                        self.evaluate_order_flow(&flows, config, &mut buy_score, &mut sell_score);
+                    }
+
+                    continue;
+                }
+
+                let data = indicator_data(config.period, config.lookback)?;
+
+                if data.is_empty() {
+                    continue;
+                }
+
                 match indicator.as_str() {
                     "ma" => self.evaluate_ma(&data, config, &mut buy_score, &mut sell_score),
                     "ema" => self.evaluate_ema(&data, config, &mut buy_score, &mut sell_score),
@@ -141,6 +213,25 @@ pub mod strategy_builder {
             Ok(data)
         }
 
+        // The latest `lookback` windows of `key`, newest first.
+        fn get_order_flow(
+            &self,
+            conn: &mut redis::Connection,
+            key: &str,
+            lookback: Option<i32>,
+        ) -> Result<Vec<OrderFlow>, Box<dyn std::error::Error>> {
+            let range_end = lookback.unwrap_or(1).max(1) as isize - 1;
+            let values: Vec<String> = conn.zrevrange(key, 0, range_end)?;
+
+            let mut flows = Vec::new();
+
+            for value in values {
+                flows.push(serde_json::from_str(&value)?);
+            }
+
+            Ok(flows)
+        }
+
         fn evaluate_ma(
             &self,
             data: &[IndicatorData],
@@ -288,6 +379,33 @@ pub mod strategy_builder {
             }
         }
 
+        // Taker imbalance averaged over the windows, amplified when the large trades lean the
+        // same way.
+        fn evaluate_order_flow(
+            &self,
+            flows: &[OrderFlow],
+            config: &IndicatorConfig,
+            buy_score: &mut f64,
+            sell_score: &mut f64,
+        ) {
+            if flows.is_empty() {
+                return;
+            }
+
+            let imbalance =
+                flows.iter().map(|flow| flow.imbalance).sum::<f64>() / flows.len() as f64;
+            let large: f64 = flows
+                .iter()
+                .map(|flow| flow.large_buy_volume - flow.large_sell_volume)
+                .sum();
+
+            if imbalance > config.threshold {
+                *buy_score += if large > 0.0 { config.weight * 1.5 } else { config.weight };
+            } else if imbalance < -config.threshold {
+                *sell_score += if large < 0.0 { config.weight * 1.5 } else { config.weight };
+            }
+        }
+
         fn generate_signal(&self, buy_score: f64, sell_score: f64) -> Signal {
             let adaptive_threshold = 1.0 + (buy_score.max(sell_score) * 0.1);
 
@@ -316,8 +434,6 @@ pub mod strategy_builder {
 
 #[cfg(test)]
 mod tests {
-    use super::*;
-
     #[test]
     fn test_load_strategy_config() {
         let json = r#"{
@@ -337,6 +453,35 @@ mod tests {
         assert_eq!(rsi_config.lookback, Some(3));
         assert_eq!(rsi_config.threshold, 3.0);
         assert_eq!(rsi_config.weight, 2.5);
+        assert_eq!(rsi_config.key, None);
+    }
+
+    #[test]
+    fn test_order_flow_config() {
+        let json = r#"{
+            "indicators": {
+                "order_flow": {
+                    "period": 1,
+                    "lookback": 5,
+                    "threshold": 0.2,
+                    "weight": 1.0,
+                    "key": "variations:order_flow:BTCUSDT:1m"
+                }
+            }
+        }"#;
+
+        let config = crate::utils::strategy_builder::strategy_builder::load_strategy_config(json).unwrap();
+        let order_flow = &config.indicators["order_flow"];
+        assert_eq!(order_flow.key.as_deref(), Some("variations:order_flow:BTCUSDT:1m"));
+        assert_eq!(config.order_flow_key(), Some("variations:order_flow:BTCUSDT:1m"));
+
+        // backtests have no order flow history to score it on.
+        let builder = crate::utils::strategy_builder::strategy_builder::StrategyBuilder::new(
+            "redis://127.0.0.1/",
+            config,
+        )
+        .unwrap();
+        assert!(builder.evaluate_history(&[]).is_err());
     }
 
     #[test]
@@ -349,6 +494,7 @@ mod tests {
                 lookback: Some(3),
                 threshold: 0.001,
                 weight: 1.0,
+                key: None,
             },
         );
 
@@ -1,36 +1,269 @@
 pub mod technical_indicators {
-    use std::fmt::format;
-    use dotenv::var;
-    use redis::{Client, Commands};
+    use redis::aio::MultiplexedConnection;
+    use redis::{Client, Commands, Pipeline, RedisResult};
+    use serde::{Deserialize, Serialize};
+    use tokio::sync::mpsc::{self, error::TrySendError};
+
+    // Writes waiting for redis, further ones are dropped rather than queued without bound.
+    const WRITER_CAPACITY: usize = 1_024;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct VariationConfig {
+        // Variations older than this are trimmed from their sorted sets, 0 keeps them all.
+        #[serde(default = "default_retention_hours")]
+        pub retention_hours: f64,
+    }
+
+    fn default_retention_hours() -> f64 {
+        24.0
+    }
+
+    impl Default for VariationConfig {
+        fn default() -> Self {
+            Self {
+                retention_hours: default_retention_hours(),
+            }
+        }
+    }
+
+    impl VariationConfig {
+        pub fn validate(&self) -> Result<(), String> {
+            if !self.retention_hours.is_finite() || self.retention_hours < 0.0 {
+                return Err(format!(
+                    "retention_hours must be zero or positive, got {}",
+                    self.retention_hours
+                ));
+            }
+
+            Ok(())
+        }
+
+        pub fn retention_ms(&self) -> i64 {
+            (self.retention_hours * 3_600_000.0) as i64
+        }
+    }
+
+    // variations:{key}:{period}, e.g. variations:price:BTCUSDT:7. The indicators:*_variation:*
+    // lists written before are left alone, a sorted set under the same name would be WRONGTYPE
+    // for their readers.
+    pub fn variations_key(key: &str, period: usize) -> String {
+        format!("variations:{}:{}", key, period)
+    }
+
+    // Stored as JSON in the variations:{key}:{period} sorted sets, scored by the time of the kline
+    // it was computed on, the same score as in klines:{symbol}:{interval}.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct Variation {
+        pub time: i64,
+        // Percentage over the period, 4 decimals.
+        pub value: f64,
+    }
 
     pub struct PriceVolume {
+        // Trims the sorted sets when positive.
+        pub retention_ms: i64,
     }
 
     impl PriceVolume {
-        fn process(&self, key: String, prices: Vec<f64>, all_periods: Vec<usize>, client: &mut Client, score: u64) {
-            let mut con = client.get_connection().unwrap();
-
-            tokio::spawn(async move {
-                for p in all_periods {
-                    let variation_percentage = (prices[0] - prices[p - 1]) / prices[p - 1];
-
-                    // save to redis
-                    let _: () = con
-                        .lpush(
-                            format!("indicators:{}:{}", key, p),
-                            format!("{:.4}", variation_percentage * 100.0),
-                        )
-                        .expect("Connection to Redis failed");
+        // Variation of the newest value (first) over each period, skipped when the oldest is zero.
+        pub fn variations(
+            values: &[f64],
+            all_periods: &[usize],
+            time: i64,
+        ) -> Vec<(usize, Variation)> {
+            all_periods
+                .iter()
+                .filter(|p| **p > 0 && **p <= values.len())
+                .filter_map(|p| {
+                    let variation_percentage = (values[0] - values[p - 1]) / values[p - 1] * 100.0;
+
+                    if !variation_percentage.is_finite() {
+                        return None;
+                    }
+
+                    let value = (variation_percentage * 10_000.0).round() / 10_000.0;
+                    Some((*p, Variation { time, value }))
+                })
+                .collect()
+        }
+
+        fn process(
+            &self,
+            key: String,
+            values: Vec<f64>,
+            all_periods: Vec<usize>,
+            writer: &VariationWriter,
+            time: i64,
+        ) {
+            let mut pipe = redis::pipe();
+
+            for (p, variation) in Self::variations(&values, &all_periods, time) {
+                let set = variations_key(&key, p);
+                pipe.zadd(&set, serde_json::to_string(&variation).unwrap(), time).ignore();
+
+                if self.retention_ms > 0 {
+                    pipe.zrembyscore(&set, "-inf", time - self.retention_ms).ignore();
                 }
+            }
+
+            writer.write(key, pipe);
+        }
+
+        pub fn process_prices(
+            &self,
+            symbol: String,
+            prices: Vec<f64>,
+            all_periods: Vec<usize>,
+            writer: &VariationWriter,
+            time: i64,
+        ) {
+            self.process(format!("price:{}", symbol), prices, all_periods, writer, time);
+        }
+
+        pub fn process_volumes(
+            &self,
+            symbol: String,
+            volumes: Vec<f64>,
+            all_periods: Vec<usize>,
+            writer: &VariationWriter,
+            time: i64,
+        ) {
+            self.process(format!("volume:{}", symbol), volumes, all_periods, writer, time);
+        }
+
+        // Variations of variations:{key}:{period} between `from` and `to` included, oldest first.
+        pub fn range(
+            client: &Client,
+            key: &str,
+            period: usize,
+            from: i64,
+            to: i64,
+        ) -> Result<Vec<Variation>, Box<dyn std::error::Error>> {
+            let mut con = client.get_connection()?;
+            let values: Vec<String> = con.zrangebyscore(variations_key(key, period), from, to)?;
+
+            let mut variations = Vec::new();
+
+            for value in values {
+                variations.push(serde_json::from_str(&value)?);
+            }
+
+            Ok(variations)
+        }
+    }
+
+    // Queues the variation writes of every symbol to a single tokio task holding one multiplexed
+    // connection, the websocket loop does not wait for redis. Outside a runtime they are written
+    // before returning.
+    #[derive(Clone)]
+    pub struct VariationWriter {
+        client: Client,
+        sender: Option<mpsc::Sender<(String, Pipeline)>>,
+    }
+
+    impl VariationWriter {
+        pub fn new(client: &Client) -> Self {
+            let sender = tokio::runtime::Handle::try_current().ok().map(|runtime| {
+                let (sender, receiver) = mpsc::channel(WRITER_CAPACITY);
+                runtime.spawn(write(client.clone(), receiver));
+                sender
             });
+
+            Self {
+                client: client.clone(),
+                sender,
+            }
         }
 
-        pub fn process_prices(&self, symbol: String, prices: Vec<f64>, all_periods: Vec<usize>, client: &mut Client, score: u64) {
-            self.process(format!("price_variation:{}", symbol), prices, all_periods, client, score);
+        fn write(&self, key: String, pipe: Pipeline) {
+            let Some(sender) = &self.sender else {
+                let result: RedisResult<()> =
+                    self.client.get_connection().and_then(|mut con| pipe.query(&mut con));
+
+                if let Err(e) = result {
+                    println!("{}, Could not save variations: {}", key, e);
+                }
+
+                return;
+            };
+
+            match sender.try_send((key, pipe)) {
+                Ok(()) => {}
+                Err(TrySendError::Full((key, _))) => {
+                    println!("{}, Variations dropped, redis is behind", key)
+                }
+                Err(TrySendError::Closed((key, _))) => {
+                    println!("{}, Variations dropped, the writer stopped", key)
+                }
+            }
         }
+    }
+
+    // Reconnects after a connection error, an outage is reported once.
+    async fn write(client: Client, mut receiver: mpsc::Receiver<(String, Pipeline)>) {
+        let mut connection: Option<MultiplexedConnection> = None;
+        let mut unavailable = false;
+
+        while let Some((key, pipe)) = receiver.recv().await {
+            if connection.is_none() {
+                match client.get_multiplexed_async_connection().await {
+                    Ok(con) => {
+                        if unavailable {
+                            println!("Redis available again for the variations");
+                        }
+
+                        connection = Some(con);
+                        unavailable = false;
+                    }
+                    Err(e) => {
+                        if !unavailable {
+                            println!("{}, Could not save variations: {}", key, e);
+                        }
+
+                        unavailable = true;
+                        continue;
+                    }
+                }
+            }
+
+            let Some(con) = connection.as_mut() else {
+                continue;
+            };
+
+            let result: RedisResult<()> = pipe.query_async(con).await;
+
+            if let Err(e) = result {
+                println!("{}, Could not save variations: {}", key, e);
+
+                if e.is_io_error() || e.is_connection_dropped() || e.is_connection_refusal() {
+                    connection = None;
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_variations() {
+            // newest first, as kept by Hooks.
+            let prices = [110.0, 105.0, 100.0, 0.0];
+
+            let variations = PriceVolume::variations(&prices, &[2, 3, 4, 28], 5_000);
+            assert_eq!(
+                variations,
+                vec![
+                    (2, Variation { time: 5_000, value: 4.7619 }),
+                    (3, Variation { time: 5_000, value: 10.0 }),
+                ]
+            );
+
+            let json = serde_json::to_string(&variations[1].1).unwrap();
+            assert_eq!(json, r#"{"time":5000,"value":10.0}"#);
 
-        pub fn process_volumes(&self, symbol: String, volumes: Vec<f64>, all_periods: Vec<usize>, client: &mut Client, score: u64) {
-            self.process(format!("volume_variation:{}", symbol), volumes, all_periods, client, score);
+            assert_eq!(variations_key("price:BTCUSDT", 7), "variations:price:BTCUSDT:7");
         }
     }
 }
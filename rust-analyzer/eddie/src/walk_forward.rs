@@ -0,0 +1,522 @@
+// Walk-forward optimization: the parameter grid of a fleet bot is backtested on a rolling training
+// window, the best set (net P&L) then trades the following unseen test window. The test windows
+// are stitched into one out-of-sample equity curve, and windows where the in-sample edge is gone
+// out of sample are flagged. Signal bots can also tune the threshold/weight of their indicators.
+//
+//   eddie walk-forward --interval 1m --csv klines_btc_usdt_1m.csv --train 7d --test 1d \
+//       --take-profit 0.05:0.3:0.05 --stop-loss 5,20,50 --strategy rsi.weight=1:2:0.5
+
+use crate::backtest::{BacktestArgs, BotReport, HistorySignals, Summary};
+use crate::fleet::{self, Bot, BotConfig};
+use crate::ledger::TradeRecord;
+use crate::metrics::Metrics;
+use crate::signal_trading::SignalTrading;
+use crate::strategy::{interval_ms, Kline};
+use crate::sweep::{self, parallel_map, Parameters};
+use crate::utils::strategy_builder::strategy_builder::{IndicatorData, StrategyConfig};
+use crate::{backtest, limits::DAY_MS};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Window {
+    pub train: Range<usize>,
+    pub test: Range<usize>,
+}
+
+// Training windows of `train_ms` followed by a test window of `test_ms`, rolled by `test_ms` so
+// the test windows follow each other. The last test window may be shorter.
+pub fn windows(klines: &[Kline], train_ms: i64, test_ms: i64) -> Vec<Window> {
+    let (Some(first), Some(last)) = (klines.first(), klines.last()) else {
+        return Vec::new();
+    };
+
+    let index = |time: i64| klines.partition_point(|kline| kline.open_time < time);
+    let mut windows = Vec::new();
+    let mut start = first.open_time;
+
+    while start + train_ms <= last.open_time {
+        let train = index(start)..index(start + train_ms);
+        let test = train.end..index(start + train_ms + test_ms);
+
+        if !train.is_empty() && !test.is_empty() {
+            windows.push(Window { train, test });
+        }
+
+        start += test_ms;
+    }
+
+    windows
+}
+
+// A parameter set, `strategy` holds "indicator.threshold" or "indicator.weight" values applied to
+// the StrategyConfig of a signal bot.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Candidate {
+    pub parameters: Parameters,
+    pub strategy: BTreeMap<String, f64>,
+}
+
+pub fn candidates(parameters: &[Parameters], strategy: &[(String, Vec<f64>)]) -> Vec<Candidate> {
+    let mut candidates: Vec<Candidate> = parameters
+        .iter()
+        .map(|parameters| Candidate {
+            parameters: *parameters,
+            strategy: BTreeMap::new(),
+        })
+        .collect();
+
+    for (key, values) in strategy {
+        candidates = candidates
+            .iter()
+            .flat_map(|candidate| {
+                values.iter().map(move |value| {
+                    let mut candidate = candidate.clone();
+                    candidate.strategy.insert(key.to_string(), *value);
+                    candidate
+                })
+            })
+            .collect();
+    }
+
+    candidates
+}
+
+pub fn tune(config: &StrategyConfig, strategy: &BTreeMap<String, f64>) -> Result<StrategyConfig, String> {
+    let mut tuned = config.clone();
+
+    for (key, value) in strategy {
+        let (name, field) = key
+            .split_once('.')
+            .ok_or_else(|| format!("{:?} must be indicator.threshold or indicator.weight", key))?;
+
+        let indicator = tuned
+            .indicators
+            .get_mut(name)
+            .ok_or_else(|| format!("no indicator {:?} in the strategy", name))?;
+
+        match field {
+            "threshold" => indicator.threshold = *value,
+            "weight" => indicator.weight = *value,
+            _ => return Err(format!("{:?} must be indicator.threshold or indicator.weight", key)),
+        }
+    }
+
+    Ok(tuned)
+}
+
+// Fleet bot the candidates are applied to, with the strategy and indicator history of a signal bot.
+pub struct Setup {
+    pub template: BotConfig,
+    pub strategy: Option<(StrategyConfig, Vec<IndicatorData>)>,
+}
+
+impl Setup {
+    fn backtest(&self, candidate: &Candidate, klines: &[Kline]) -> BotReport {
+        let mut trader = candidate.parameters.bot_config(&self.template).build();
+        trader.persist = false;
+
+        let bot = match (&self.template.signal, &self.strategy) {
+            (Some(signal), Some((strategy, history))) => {
+                // the keys are checked by `tune` before the walk starts.
+                let strategy = tune(strategy, &candidate.strategy).unwrap();
+                let source = HistorySignals::new(strategy, history.clone(), signal.max_age_ms());
+
+                Bot::Signal(SignalTrading::new(trader, &signal.interval, Box::new(source)))
+            }
+            _ => Bot::Movement(trader),
+        };
+
+        backtest::run(&mut [bot], klines).remove(0)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WindowResult {
+    pub train_from: i64,
+    pub test_from: i64,
+    pub test_to: i64,
+    pub candidate: Candidate,
+    pub in_sample: Summary,
+    pub out_of_sample: Summary,
+    // Net P&L per day, windows differ in length.
+    pub in_sample_daily: f64,
+    pub out_of_sample_daily: f64,
+    // The set made money in sample but not on the unseen window.
+    pub degraded: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct EquityPoint {
+    pub time: i64,
+    pub equity: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WalkForward {
+    pub windows: Vec<WindowResult>,
+    // Cumulative out-of-sample net P&L at every trade exit.
+    pub curve: Vec<EquityPoint>,
+    pub summary: Summary,
+}
+
+fn daily(summary: &Summary, klines: &[Kline]) -> f64 {
+    let days = sweep::dataset_days(klines);
+
+    if days > 0.0 {
+        summary.net_pnl / days
+    } else {
+        0.0
+    }
+}
+
+// Best in-sample report: the highest net P&L, on a tie the fewest trades (the same result for
+// less costs and exposure), then the highest profit factor, then the first of the grid order.
+fn best(reports: &[BotReport]) -> Option<usize> {
+    (0..reports.len()).min_by(|a, b| {
+        let (a, b) = (&reports[*a].summary, &reports[*b].summary);
+
+        b.net_pnl
+            .total_cmp(&a.net_pnl)
+            .then(a.trades.cmp(&b.trades))
+            .then(b.profit_factor.total_cmp(&a.profit_factor))
+    })
+}
+
+pub fn walk_forward(
+    setup: &Setup,
+    candidates: &[Candidate],
+    klines: &[Kline],
+    train_ms: i64,
+    test_ms: i64,
+    threads: usize,
+) -> Result<WalkForward, String> {
+    if candidates.is_empty() {
+        return Err("no parameter set to walk forward".to_string());
+    }
+
+    let mut results = Vec::new();
+    let mut trades: Vec<TradeRecord> = Vec::new();
+
+    for window in windows(klines, train_ms, test_ms) {
+        let train = &klines[window.train.clone()];
+        let test = &klines[window.test.clone()];
+
+        let reports = parallel_map(candidates, threads, |candidate| setup.backtest(candidate, train));
+
+        let best = best(&reports).ok_or("no in-sample report")?;
+
+        let in_sample = reports[best].summary.clone();
+        let report = setup.backtest(&candidates[best], test);
+
+        let in_sample_daily = daily(&in_sample, train);
+        let out_of_sample_daily = daily(&report.summary, test);
+
+        results.push(WindowResult {
+            train_from: train[0].open_time,
+            test_from: test[0].open_time,
+            test_to: test[test.len() - 1].close_time,
+            candidate: candidates[best].clone(),
+            degraded: in_sample.net_pnl > 0.0 && out_of_sample_daily <= 0.0,
+            in_sample,
+            out_of_sample: report.summary,
+            in_sample_daily,
+            out_of_sample_daily,
+        });
+
+        trades.extend(report.trades);
+    }
+
+    let mut curve = Vec::new();
+    let mut equity = 0.0;
+    let mut peak: f64 = 0.0;
+    let mut max_drawdown: f64 = 0.0;
+
+    for trade in &trades {
+        equity += trade.net_pnl;
+        peak = peak.max(equity);
+        max_drawdown = max_drawdown.max(peak - equity);
+
+        curve.push(EquityPoint {
+            time: trade.exit_time,
+            equity,
+        });
+    }
+
+    Ok(WalkForward {
+        windows: results,
+        curve,
+        summary: Summary::new(
+            &Metrics::from_ledger(&trades, setup.template.balance),
+            max_drawdown,
+            setup.template.balance + equity,
+        ),
+    })
+}
+
+fn date(time: i64) -> String {
+    chrono::DateTime::from_timestamp_millis(time)
+        .map(|date| date.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+pub fn print_walk_forward(walk: &WalkForward) {
+    println!(
+        "{:>3} {:<16} {:<16} {:>8} {:>8} {:>8} {:>7} {:>12} {:>7} {:>12}  strategy",
+        "#", "train from", "test from", "tp %", "sl $", "watch %", "is #", "is net", "oos #", "oos net"
+    );
+
+    for (index, window) in walk.windows.iter().enumerate() {
+        let parameters = &window.candidate.parameters;
+
+        println!(
+            "{:>3} {:<16} {:<16} {:>8} {:>8} {:>8} {:>7} {:>12.3} {:>7} {:>12.3}  {:?}{}",
+            index + 1,
+            date(window.train_from),
+            date(window.test_from),
+            parameters.take_profit_percentage,
+            parameters.stop_loss_usd,
+            parameters.watch_movement_percentage,
+            window.in_sample.trades,
+            window.in_sample.net_pnl,
+            window.out_of_sample.trades,
+            window.out_of_sample.net_pnl,
+            window.candidate.strategy,
+            if window.degraded { "  DEGRADED" } else { "" }
+        );
+    }
+
+    let summary = &walk.summary;
+
+    println!(
+        "VHAKM: Out of sample: {} trades, win rate {:.1}%, net {:.3}, profit factor {:.2}, max drawdown {:.3}, {} of {} windows degraded",
+        summary.trades,
+        summary.win_rate,
+        summary.net_pnl,
+        summary.profit_factor,
+        summary.max_drawdown,
+        walk.windows.iter().filter(|window| window.degraded).count(),
+        walk.windows.len()
+    );
+}
+
+fn duration_ms(flag: &str, value: &str) -> Result<i64, String> {
+    interval_ms(value)
+        .filter(|ms| *ms > 0)
+        .ok_or_else(|| format!("{} expects a duration such as 12h or 7d, got {}", flag, value))
+}
+
+pub fn run_cli(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut train_ms = 7 * DAY_MS;
+    let mut test_ms = DAY_MS;
+    let mut take_profits = None;
+    let mut stop_losses = None;
+    let mut watches = None;
+    let mut strategy = Vec::new();
+    let mut base = None;
+    let mut threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+    let mut output = None;
+
+    let mut data_args = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", flag))?
+            .to_string();
+
+        match flag.as_str() {
+            "--train" => train_ms = duration_ms(flag, &value)?,
+            "--test" => test_ms = duration_ms(flag, &value)?,
+            "--take-profit" => take_profits = Some(sweep::parse_range(&value)?),
+            "--stop-loss" => stop_losses = Some(sweep::parse_range(&value)?),
+            "--watch" => watches = Some(sweep::parse_range(&value)?),
+            "--strategy" => {
+                let (key, range) = value
+                    .split_once('=')
+                    .ok_or_else(|| format!("--strategy expects indicator.field=range, got {}", value))?;
+                strategy.push((key.to_string(), sweep::parse_range(range)?));
+            }
+            "--base" => base = Some(value),
+            "--threads" => threads = value.parse()?,
+            "--output" => output = Some(value),
+            _ => data_args.extend([flag.to_string(), value]),
+        }
+    }
+
+    let data = BacktestArgs::parse(&data_args)?;
+    let config = fleet::load_fleet_config(&data.fleet)?;
+    let template = sweep::base_bot(&config, base.as_deref(), &data)?.clone();
+
+    let parameters = sweep::grid(
+        &take_profits.unwrap_or(vec![template.take_profit_percentage]),
+        &stop_losses.unwrap_or(vec![template.stop_loss_usd]),
+        &watches.unwrap_or(vec![template.watch_movement_percentage]),
+    );
+
+    let candidates = candidates(&parameters, &strategy);
+
+    let strategy_setup = match &template.signal {
+        Some(signal) => {
+            let config = backtest::history_strategy(&signal.strategy)?;
+
+            for candidate in &candidates {
+                tune(&config, &candidate.strategy)?;
+            }
+
+            Some((config, data.indicator_history(&signal.key)?))
+        }
+        None if !strategy.is_empty() => {
+            return Err(format!("--strategy needs a signal bot, {} is not one", template.name).into());
+        }
+        None => None,
+    };
+
+    for candidate in &candidates {
+        candidate.parameters.bot_config(&template).validate()?;
+    }
+
+    let klines = data.klines()?;
+
+    println!(
+        "VHAKM: Walking {} parameter sets of {} forward on {} {} klines ({:.1} days)",
+        candidates.len(),
+        template.name,
+        klines.len(),
+        data.interval,
+        sweep::dataset_days(&klines)
+    );
+
+    let setup = Setup {
+        template,
+        strategy: strategy_setup,
+    };
+
+    let walk = walk_forward(&setup, &candidates, &klines, train_ms, test_ms, threads)?;
+    print_walk_forward(&walk);
+
+    if let Some(path) = &output {
+        std::fs::write(path, serde_json::to_string_pretty(&walk)?)?;
+        println!("VHAKM: Walk-forward written to {}", path);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::strategy_builder::strategy_builder::load_strategy_config;
+
+    fn klines(closes: &[f64]) -> Vec<Kline> {
+        closes
+            .iter()
+            .enumerate()
+            .map(|(second, close)| Kline {
+                symbol: "BTCUSDT".to_string(),
+                interval: "1s".to_string(),
+                open_time: second as i64 * 1_000,
+                close_time: second as i64 * 1_000 + 999,
+                open: *close,
+                high: *close,
+                low: *close,
+                close: *close,
+                volume: 1.0,
+                closed: true,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_windows() {
+        let klines = klines(&[100_000.0; 25]);
+        let windows = windows(&klines, 10_000, 5_000);
+
+        assert_eq!(windows.len(), 3);
+        assert_eq!(windows[0], Window { train: 0..10, test: 10..15 });
+        assert_eq!(windows[1], Window { train: 5..15, test: 15..20 });
+        assert_eq!(windows[2], Window { train: 10..20, test: 20..25 });
+    }
+
+    #[test]
+    fn test_tune_strategy() {
+        let config = load_strategy_config(r#"{"indicators": {"rsi": {"period": 14, "threshold": 3.0, "weight": 1.5}}}"#)
+            .unwrap();
+
+        let tuned = candidates(&sweep::grid(&[0.03], &[5.0], &[4.0]), &[("rsi.weight".to_string(), vec![1.0, 2.0])]);
+        assert_eq!(tuned.len(), 2);
+        assert_eq!(tune(&config, &tuned[1].strategy).unwrap().indicators["rsi"].weight, 2.0);
+
+        let unknown = BTreeMap::from([("ma.weight".to_string(), 1.0)]);
+        assert!(tune(&config, &unknown).is_err());
+    }
+
+    #[test]
+    fn test_best_tie_break() {
+        let report = |net_pnl: f64, trades: usize, profit_factor: f64| BotReport {
+            name: "test:BTCUSDT:A".to_string(),
+            family: "vhakm".to_string(),
+            summary: Summary::new(
+                &Metrics {
+                    trades,
+                    net_pnl,
+                    profit_factor,
+                    ..Default::default()
+                },
+                0.0,
+                0.0,
+            ),
+            metrics: Metrics::default(),
+            trades: Vec::new(),
+        };
+
+        assert_eq!(best(&[]), None);
+        assert_eq!(best(&[report(5.0, 3, 2.0), report(8.0, 9, 1.5)]), Some(1));
+        assert_eq!(best(&[report(8.0, 9, 1.5), report(8.0, 4, 1.2)]), Some(1));
+        assert_eq!(best(&[report(8.0, 4, 1.2), report(8.0, 4, 3.0)]), Some(1));
+        assert_eq!(best(&[report(8.0, 4, 3.0), report(8.0, 4, 3.0)]), Some(0));
+    }
+
+    #[test]
+    fn test_walk_forward_is_out_of_sample() {
+        // steady rises the 0.1% take profit rides, then a whipsaw that stops every entry out.
+        let mut closes: Vec<f64> = (0..20).map(|second| 100_000.0 + second as f64 * 50.0).collect();
+        closes.extend([100_950.0, 101_000.0, 100_990.0, 100_900.0, 100_950.0, 101_000.0, 100_990.0, 100_900.0, 100_950.0, 101_000.0]);
+
+        let template: BotConfig = serde_json::from_str(
+            r#"{"name": "test:BTCUSDT:A", "family": "vhakm", "symbol": "BTCUSDT", "take_profit_percentage": 0.03,
+                "stop_loss_usd": 5.0, "watch_movement_percentage": 4.0}"#,
+        )
+        .unwrap();
+
+        let setup = Setup {
+            template,
+            strategy: None,
+        };
+
+        let klines = klines(&closes);
+        let candidates = candidates(&sweep::grid(&[0.03, 0.1], &[5.0, 80.0], &[4.0]), &[]);
+        let walk = walk_forward(&setup, &candidates, &klines, 10_000, 10_000, 2).unwrap();
+
+        assert_eq!(walk.windows.len(), 2);
+
+        // every trade of the curve happened inside a test window.
+        assert!(walk
+            .curve
+            .iter()
+            .all(|point| point.time >= walk.windows[0].test_from));
+        assert_eq!(walk.curve.last().unwrap().equity, walk.summary.net_pnl);
+        assert_eq!(
+            walk.summary.net_pnl,
+            walk.windows.iter().map(|window| window.out_of_sample.net_pnl).sum::<f64>()
+        );
+
+        assert!(!walk.windows[0].degraded);
+        assert!(walk.windows[1].in_sample.net_pnl > 0.0);
+        assert!(walk.windows[1].degraded);
+
+        assert!(walk_forward(&setup, &[], &klines, 10_000, 10_000, 2).is_err());
+    }
+}